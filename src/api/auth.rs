@@ -7,7 +7,7 @@ use std::{
 use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::data::config::Config;
+use crate::data::config::{Backend, Config};
 
 use super::Error;
 
@@ -17,7 +17,97 @@ pub struct Client {
 	pub client_secret: String,
 }
 
-const SCOPES: &'static str = "write:statuses";
+const SCOPES: &[&str] = &["write:statuses", "write:media"];
+const READ_SCOPES: &[&str] = &["read:statuses"];
+const MENTION_SCOPES: &[&str] = &["read:notifications"];
+const PROFILE_SCOPES: &[&str] = &["write:accounts"];
+/// What tokens were given before the bot asked for any other scopes.
+pub const LEGACY_SCOPES: &str = "write:statuses";
+
+/// Opens `url` in a browser for the user to approve the bot, then waits
+/// to be redirected back to it, returning the path it was redirected
+/// to.
+pub fn callback(config: &Config, url: &str) -> Result<String> {
+	if let Err(e) = open::that(url) {
+		let res = match env::var("BROWSER") {
+			Ok(browser) => open::with(url, browser),
+			Err(e_) => {
+				log::error!("{e_}");
+				Err(e)
+			},
+		};
+		if res.is_err() {
+			log::error!(
+				"{}",
+				res.context("Could not open browser.").unwrap_err()
+			);
+		}
+	}
+	println!(
+		"Waiting for authentication...\n \
+		If your browser does not open automatically, sign in at {url}"
+	);
+
+	let listener =
+		TcpListener::bind(format!("127.0.0.1:{}", config.port))?;
+	let (mut connection, _) = listener.accept()?;
+	let mut buf = [0_u8; 256];
+	let _ = connection.read(&mut buf)?;
+
+	let mut headers = [httparse::EMPTY_HEADER; 32];
+	let mut http = httparse::Request::new(&mut headers);
+	http.parse(&buf)?;
+	// this is hideous but rustfmt likes it for some reason
+	connection.write_all(
+		format!(
+			"
+			HTTP/1.1 303 See Other\r\
+			Location: {}\r
+			",
+			super::url(&config.instance, "")
+		)
+		.trim()
+		.as_bytes(),
+	)?;
+	if let Err(e) = connection.shutdown(Shutdown::Both) {
+		log::warn!("{e}");
+	}
+
+	Ok(http
+		.path
+		.ok_or(anyhow!("Did not receive authorisation code."))?
+		.to_owned())
+}
+
+/// The scopes needed to do everything the config asks for.
+pub fn scopes(config: &Config) -> String {
+	match config.backend {
+		Backend::Mastodon => {},
+		Backend::Misskey => {
+			return super::misskey::PERMISSIONS.join(" ")
+		},
+		// app passwords can do anything but change account settings
+		Backend::Bluesky => return String::new(),
+	}
+	let mut scopes = SCOPES.to_vec();
+	// statuses are only read back for scheduled posts, poll results
+	// and how posts are doing
+	if config.scheduling
+		|| config.poll.enable
+		|| config.stats.enable
+		|| config.retention.favourites.is_some()
+	{
+		scopes.extend(READ_SCOPES);
+	}
+	if config.mentions.enable {
+		scopes.extend(MENTION_SCOPES);
+	}
+	if config.profile.is_some() {
+		scopes.extend(PROFILE_SCOPES);
+	}
+	scopes.join(" ")
+}
+
 impl Client {
 	// POST /api/v1/apps
 	pub fn new(config: &Config) -> Result<Self> {
@@ -25,24 +115,24 @@ impl Client {
 		struct Request<'a> {
 			client_name: &'a str,
 			redirect_uris: &'a str,
-			scopes: &'static str,
+			scopes: &'a str,
 			website: &'static str,
 		}
 		log::info!("Requesting a new client session.");
-		let rq_client = super::RQ_CLIENT.get().unwrap();
 		let params = Request {
 			client_name: &config.client,
 			redirect_uris: &format!(
 				"http://127.0.0.1:{}",
 				config.port
 			),
-			scopes: SCOPES,
+			scopes: &scopes(config),
 			website: env!("CARGO_PKG_REPOSITORY"),
 		};
-		let response = rq_client
-			.post(format!("https://{}/api/v1/apps", config.instance))
-			.form(&params)
-			.send()?;
+		let response = super::request::send(|client| {
+			client
+				.post(super::url(&config.instance, "/api/v1/apps"))
+				.form(&params)
+		})?;
 		let success = response.status().is_success();
 		let text = response.text()?;
 		if success {
@@ -63,7 +153,7 @@ impl Client {
 			response_type: &'static str,
 			client_id: &'a str,
 			redirect_uri: &'a str,
-			scope: &'static str,
+			scope: &'a str,
 			lang: &'a str,
 		}
 		log::info!("Authorising user");
@@ -74,63 +164,15 @@ impl Client {
 				"http://127.0.0.1:{}",
 				config.port
 			),
-			scope: SCOPES,
+			scope: &scopes(config),
 			lang: &config.lang,
 		};
 		let url = format!(
-			"https://{}/oauth/authorize?{}",
-			config.instance,
+			"{}?{}",
+			super::url(&config.instance, "/oauth/authorize"),
 			serde_qs::to_string(&params)?
 		);
-		if let Err(e) = open::that(&url) {
-			let res = match env::var("BROWSER") {
-				Ok(browser) => open::with(&url, browser),
-				Err(e_) => {
-					log::error!("{e_}");
-					Err(e)
-				},
-			};
-			if res.is_err() {
-				log::error!(
-					"{}",
-					res.context("Could not open browser.")
-						.unwrap_err()
-				);
-			}
-		}
-		println!(
-			"Waiting for authentication...\n \
-			If your browser does not open automatically, sign in at {url}"
-		);
-
-		let listener =
-			TcpListener::bind(format!("127.0.0.1:{}", config.port))?;
-		let (mut connection, _) = listener.accept()?;
-		let mut buf = [0_u8; 256];
-		connection.read(&mut buf)?;
-
-		let mut headers = [httparse::EMPTY_HEADER; 32];
-		let mut http = httparse::Request::new(&mut headers);
-		http.parse(&buf)?;
-		// this is hideous but rustfmt likes it for some reason
-		connection.write_all(
-			format!(
-				"
-				HTTP/1.1 303 See Other\r\
-				Location: https://{}\r
-				",
-				config.instance
-			)
-			.trim()
-			.as_bytes(),
-		)?;
-		if let Err(e) = connection.shutdown(Shutdown::Both) {
-			log::warn!("{e}");
-		}
-
-		let path = http
-			.path
-			.ok_or(anyhow!("Did not receive authorisation code."))?;
+		let path = callback(config, &url)?;
 		let start = path
 			.find("?code=")
 			.ok_or(anyhow!("Authorisation code is not present."))?
@@ -150,7 +192,7 @@ impl Client {
 			client_id: &'a str,
 			client_secret: &'a str,
 			redirect_uri: &'a str,
-			scope: &'static str,
+			scope: &'a str,
 		}
 		#[derive(Deserialize)]
 		struct Response<'a> {
@@ -159,7 +201,6 @@ impl Client {
 		let code = self.authorise(config)?;
 		log::info!("Requesting a new token");
 
-		let rq_client = super::RQ_CLIENT.get().unwrap();
 		let params = Request {
 			grant_type: "authorization_code",
 			code: &code,
@@ -169,12 +210,13 @@ impl Client {
 				"http://127.0.0.1:{}",
 				config.port
 			),
-			scope: SCOPES,
+			scope: &scopes(config),
 		};
-		let response = rq_client
-			.post(format!("https://{}/oauth/token", config.instance))
-			.form(&params)
-			.send()?;
+		let response = super::request::send(|client| {
+			client
+				.post(super::url(&config.instance, "/oauth/token"))
+				.form(&params)
+		})?;
 		let success = response.status().is_success();
 		let text = response.text()?;
 		if success {
@@ -209,19 +251,16 @@ impl Client {
 			token: String,
 		}
 		log::info!("Revoking authorisation token.");
-		let rq_client = super::RQ_CLIENT.get().unwrap();
 		let params = Request {
 			client_id: self.client_id,
 			client_secret: self.client_secret,
 			token,
 		};
-		let response = rq_client
-			.post(format!(
-				"https://{}/oauth/revoke",
-				instance.as_ref()
-			))
-			.form(&params)
-			.send()?;
+		let response = super::request::send(|client| {
+			client
+				.post(super::url(instance.as_ref(), "/oauth/revoke"))
+				.form(&params)
+		})?;
 		if !response.status().is_success() {
 			let text = response.text()?;
 			let err = serde_json::from_str::<super::Error>(&text)
@@ -247,16 +286,15 @@ pub fn verify(
 	instance: impl AsRef<str>,
 ) -> Result<()> {
 	log::info!("Verifying authorisation token.");
-	super::RQ_CLIENT
-		.get()
-		.unwrap()
-		.get(format!(
-			"https://{}/api/v1/apps/verify_credentials",
-			instance.as_ref()
-		))
-		.header("Authorization", token.as_ref())
-		.send()?
-		.error_for_status()
-		.context("Authorisation token failed verification.")?;
+	super::request::send(|client| {
+		client
+			.get(super::url(
+				instance.as_ref(),
+				"/api/v1/apps/verify_credentials",
+			))
+			.header("Authorization", token.as_ref())
+	})?
+	.error_for_status()
+	.context("Authorisation token failed verification.")?;
 	Ok(())
 }
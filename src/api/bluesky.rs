@@ -0,0 +1,688 @@
+// Bluesky, or any other AT Protocol server. Rather than being given a
+// token, the bot logs in with an app password, getting a short lived
+// access token along with a longer lived one to refresh it with.
+
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{SecondsFormat, Utc};
+use rand::Rng;
+use regex::Regex;
+use reqwest::blocking::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::post::Response;
+use crate::data::{
+	card::{HEIGHT, WIDTH},
+	config::Config,
+	history::Engagement,
+	secrets::Secrets,
+};
+
+const COLLECTION: &str = "app.bsky.feed.post";
+// Record keys are timestamps, written in this sortable base32.
+const TID_ALPHABET: &[u8] = b"234567abcdefghijklmnopqrstuvwxyz";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+	pub did: String,
+	pub handle: String,
+	pub access_jwt: String,
+	pub refresh_jwt: String,
+}
+
+#[derive(Deserialize)]
+struct XrpcError {
+	error: String,
+	message: Option<String>,
+}
+
+fn xrpc(instance: &str, method: &str) -> String {
+	super::url(instance, &format!("/xrpc/{method}"))
+}
+
+/// The error an XRPC server explained a failed request with, if any.
+fn explain(text: &str) -> Option<XrpcError> {
+	serde_json::from_str::<XrpcError>(text).ok()
+}
+
+// POST /xrpc/com.atproto.server.createSession
+pub fn login(config: &Config) -> Result<Session> {
+	let handle = config
+		.bluesky
+		.handle
+		.as_deref()
+		.ok_or(anyhow!("`bluesky.handle` isn't set."))?;
+	let password = match &config.bluesky.password {
+		Some(password) => password.clone(),
+		None => rpassword::prompt_password(format!(
+			"App password for {handle}: "
+		))?,
+	};
+	log::info!("Logging in as {handle}");
+	let body = json!({ "identifier": handle, "password": password })
+		.to_string();
+	let response = super::request::send(|client| {
+		client
+			.post(xrpc(
+				&config.instance,
+				"com.atproto.server.createSession",
+			))
+			.header("Content-Type", "application/json")
+			.body(body.clone())
+	})?;
+	let failed = response.error_for_status_ref().err();
+	let text = response.text()?;
+	if let Some(e) = failed {
+		let reason = explain(&text)
+			.and_then(|e| e.message)
+			.unwrap_or_else(|| String::from("no reason given"));
+		return Err(anyhow::Error::from(e).context(format!(
+			"Failed to log in as {handle}: {reason}"
+		)));
+	}
+	Ok(serde_json::from_str::<Session>(&text)?)
+}
+
+// POST /xrpc/com.atproto.server.refreshSession
+/// Swaps the refresh token for a new session, logging in again if the
+/// refresh token has expired too. The cache is saved straight away,
+/// since the old refresh token can't be used again.
+fn refresh(config: &Config, secrets: &Secrets) -> Result<()> {
+	let lock = secrets
+		.session
+		.as_ref()
+		.ok_or(anyhow!("Not logged in to Bluesky."))?;
+	log::info!("Refreshing session");
+	let refresh =
+		format!("Bearer {}", lock.lock().unwrap().refresh_jwt);
+	let refreshed = super::request::send(|client| {
+		client
+			.post(xrpc(
+				&config.instance,
+				"com.atproto.server.refreshSession",
+			))
+			.header("Authorization", &refresh)
+	})
+	.and_then(|response| Ok(response.error_for_status()?.text()?))
+	.and_then(|text| Ok(serde_json::from_str::<Session>(&text)?));
+	let session = match refreshed {
+		Ok(session) => session,
+		Err(e) => {
+			log::warn!("Failed to refresh session: {e}");
+			login(config)?
+		},
+	};
+	*lock.lock().unwrap() = session;
+	if config.cache.enable {
+		secrets.dump(config).context("Failed to save cache.")?;
+	}
+	Ok(())
+}
+
+/// Makes an authorised request, refreshing the session and trying
+/// again if the access token has expired.
+fn call(
+	config: &Config,
+	secrets: &Secrets,
+	make: impl Fn(&Client) -> RequestBuilder,
+) -> Result<String> {
+	let lock = secrets
+		.session
+		.as_ref()
+		.ok_or(anyhow!("Not logged in to Bluesky."))?;
+	let mut refreshed = false;
+	loop {
+		let token =
+			format!("Bearer {}", lock.lock().unwrap().access_jwt);
+		let response = super::request::send(|client| {
+			make(client).header("Authorization", &token)
+		})?;
+		let failed = response.error_for_status_ref().err();
+		let text = response.text()?;
+		let Some(e) = failed else {
+			return Ok(text);
+		};
+		match explain(&text) {
+			Some(xrpc)
+				if xrpc.error == "ExpiredToken" && !refreshed =>
+			{
+				refresh(config, secrets)?;
+				refreshed = true;
+			},
+			Some(XrpcError {
+				error,
+				message: Some(message),
+			}) => {
+				return Err(anyhow::Error::from(e)
+					.context(format!("{error}: {message}")))
+			},
+			_ => return Err(e.into()),
+		}
+	}
+}
+
+// GET /xrpc/com.atproto.server.getSession
+pub fn verify(config: &Config, secrets: &Secrets) -> Result<()> {
+	log::info!("Verifying session.");
+	call(config, secrets, |client| {
+		client.get(xrpc(
+			&config.instance,
+			"com.atproto.server.getSession",
+		))
+	})
+	.context("Session failed verification.")?;
+	Ok(())
+}
+
+// POST /xrpc/com.atproto.server.deleteSession
+pub fn logout(config: &Config, secrets: &Secrets) -> Result<()> {
+	let Some(lock) = &secrets.session else {
+		return Ok(());
+	};
+	log::info!("Logging out");
+	let refresh =
+		format!("Bearer {}", lock.lock().unwrap().refresh_jwt);
+	super::request::send(|client| {
+		client
+			.post(xrpc(
+				&config.instance,
+				"com.atproto.server.deleteSession",
+			))
+			.header("Authorization", &refresh)
+	})?
+	.error_for_status()
+	.context("Failed to log out.")?;
+	Ok(())
+}
+
+// POST /xrpc/com.atproto.repo.uploadBlob
+/// Uploads a PNG, returning the image to embed in a post as JSON, so
+/// that it can be passed around like the media ids of other backends.
+pub fn upload(
+	config: &Config,
+	secrets: &Secrets,
+	image: Vec<u8>,
+	description: &str,
+) -> Result<String> {
+	#[derive(Deserialize)]
+	struct Uploaded {
+		blob: Value,
+	}
+	log::info!("Uploading card");
+	let text = call(config, secrets, |client| {
+		client
+			.post(xrpc(
+				&config.instance,
+				"com.atproto.repo.uploadBlob",
+			))
+			.header("Content-Type", "image/png")
+			.body(image.clone())
+	})
+	.context("Failed to upload card.")?;
+	let blob = serde_json::from_str::<Uploaded>(&text)?.blob;
+	Ok(json!({
+		"alt": description,
+		"image": blob,
+		"aspectRatio": { "width": WIDTH, "height": HEIGHT },
+	})
+	.to_string())
+}
+
+/// A record key made from the current time, so that posts sort in the
+/// order they were made. The last ten bits are random, so two bots
+/// posting at the same moment don't clash.
+fn tid() -> String {
+	let micros = Utc::now().timestamp_micros() as u64;
+	let clock = rand::thread_rng().gen_range(0..1024);
+	let mut n = (micros << 10) | clock;
+	let mut out = [0; 13];
+	for c in out.iter_mut().rev() {
+		*c = TID_ALPHABET[(n & 31) as usize];
+		n >>= 5;
+	}
+	String::from_utf8(out.to_vec()).unwrap()
+}
+
+// GET /xrpc/com.atproto.identity.resolveHandle
+fn resolve(config: &Config, handle: &str) -> Result<String> {
+	#[derive(Deserialize)]
+	struct Resolved {
+		did: String,
+	}
+	let text = super::request::send(|client| {
+		client
+			.get(xrpc(
+				&config.instance,
+				"com.atproto.identity.resolveHandle",
+			))
+			.query(&[("handle", handle)])
+	})?
+	.error_for_status()?
+	.text()?;
+	Ok(serde_json::from_str::<Resolved>(&text)?.did)
+}
+
+fn facet(start: usize, end: usize, feature: Value) -> Value {
+	json!({
+		"index": { "byteStart": start, "byteEnd": end },
+		"features": [feature],
+	})
+}
+
+/// Bluesky doesn't find links, tags or mentions in the text itself,
+/// so they have to be marked out by their byte offsets. Mentions of
+/// handles which can't be resolved are left as plain text.
+fn facets(config: &Config, text: &str) -> Vec<Value> {
+	static LINK: OnceLock<Regex> = OnceLock::new();
+	static TAG: OnceLock<Regex> = OnceLock::new();
+	static MENTION: OnceLock<Regex> = OnceLock::new();
+	let link =
+		LINK.get_or_init(|| Regex::new(r"https?://[^\s]+").unwrap());
+	let tag = TAG
+		.get_or_init(|| Regex::new(r"(?:^|\s)(#[^\s#]+)").unwrap());
+	let mention = MENTION.get_or_init(|| {
+		Regex::new(r"(?:^|\s)@([a-zA-Z0-9-]+(?:\.[a-zA-Z0-9-]+)+)")
+			.unwrap()
+	});
+	let trailing = ['.', ',', ';', ':', '!', '?', ')', '\'', '"'];
+
+	let mut out = Vec::new();
+	for found in link.find_iter(text) {
+		let uri = found.as_str().trim_end_matches(trailing);
+		out.push(facet(
+			found.start(),
+			found.start() + uri.len(),
+			json!({
+				"$type": "app.bsky.richtext.facet#link",
+				"uri": uri,
+			}),
+		));
+	}
+	for caps in tag.captures_iter(text) {
+		let found = caps.get(1).unwrap();
+		let name = found.as_str().trim_end_matches(trailing);
+		// tags can't be only numbers
+		if name[1..].chars().all(|c| c.is_ascii_digit()) {
+			continue;
+		}
+		out.push(facet(
+			found.start(),
+			found.start() + name.len(),
+			json!({
+				"$type": "app.bsky.richtext.facet#tag",
+				"tag": &name[1..],
+			}),
+		));
+	}
+	for caps in mention.captures_iter(text) {
+		let found = caps.get(1).unwrap();
+		let handle = found.as_str();
+		match resolve(config, handle) {
+			Ok(did) => out.push(facet(
+				found.start() - 1,
+				found.start() + handle.len(),
+				json!({
+					"$type": "app.bsky.richtext.facet#mention",
+					"did": did,
+				}),
+			)),
+			Err(e) => log::warn!("Failed to resolve @{handle}: {e}"),
+		}
+	}
+	out
+}
+
+// POST /xrpc/com.atproto.repo.createRecord
+/// Builds the body for a post, with the images made by [`upload`].
+/// The record key is picked here rather than by the server, so
+/// sending the same body again is turned down instead of making
+/// another post.
+pub fn body(
+	config: &Config,
+	secrets: &Secrets,
+	text: &str,
+	images: &[String],
+) -> Result<String> {
+	let session = secrets
+		.session
+		.as_ref()
+		.ok_or(anyhow!("Not logged in to Bluesky."))?;
+	let did = session.lock().unwrap().did.clone();
+	let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+	let mut record = json!({
+		"$type": COLLECTION,
+		"text": text,
+		"createdAt": now,
+	});
+	if let Some(language) = &config.language {
+		record["langs"] = json!([language]);
+	}
+	let facets = facets(config, text);
+	if !facets.is_empty() {
+		record["facets"] = json!(facets);
+	}
+	if !images.is_empty() {
+		let images = images
+			.iter()
+			.map(|image| serde_json::from_str::<Value>(image))
+			.collect::<Result<Vec<_>, _>>()?;
+		record["embed"] = json!({
+			"$type": "app.bsky.embed.images",
+			"images": images,
+		});
+	}
+	Ok(json!({
+		"repo": did,
+		"collection": COLLECTION,
+		"rkey": tid(),
+		"record": record,
+	})
+	.to_string())
+}
+
+#[derive(Deserialize)]
+struct Created {
+	uri: String,
+}
+
+// GET /xrpc/com.atproto.repo.getRecord
+/// Finds the post a body was for, if it's already been made.
+fn existing(
+	config: &Config,
+	secrets: &Secrets,
+	body: &str,
+) -> Option<String> {
+	let body = serde_json::from_str::<Value>(body).ok()?;
+	let query = ["repo", "collection", "rkey"]
+		.map(|key| (key, body[key].as_str().unwrap_or_default()));
+	let text = call(config, secrets, |client| {
+		client
+			.get(xrpc(&config.instance, "com.atproto.repo.getRecord"))
+			.query(&query)
+	})
+	.ok()?;
+	Some(serde_json::from_str::<Created>(&text).ok()?.uri)
+}
+
+/// Makes a post from a body built by [`body`]. If it fails because an
+/// earlier attempt already made the post, that post is returned.
+pub fn create(
+	config: &Config,
+	secrets: &Secrets,
+	body: &str,
+) -> Result<Response> {
+	let created = call(config, secrets, |client| {
+		client
+			.post(xrpc(
+				&config.instance,
+				"com.atproto.repo.createRecord",
+			))
+			.header("Content-Type", "application/json")
+			.body(body.to_owned())
+	});
+	let uri = match created {
+		Ok(text) => serde_json::from_str::<Created>(&text)?.uri,
+		Err(e) => match existing(config, secrets, body) {
+			Some(uri) => {
+				log::info!("The post was already made.");
+				uri
+			},
+			None => return Err(e),
+		},
+	};
+	let rkey = uri.rsplit('/').next().unwrap_or_default();
+	let handle = match &secrets.session {
+		Some(session) => session.lock().unwrap().handle.clone(),
+		None => String::new(),
+	};
+	Ok(Response {
+		url: Some(format!(
+			"https://bsky.app/profile/{handle}/post/{rkey}"
+		)),
+		id: uri,
+		poll: None,
+	})
+}
+
+// GET /xrpc/app.bsky.feed.getPosts
+/// How people have interacted with a post, or `None` if it's gone.
+pub fn engagement(
+	config: &Config,
+	secrets: &Secrets,
+	uri: &str,
+) -> Result<Option<Engagement>> {
+	#[derive(Deserialize)]
+	#[serde(rename_all = "camelCase")]
+	struct Post {
+		#[serde(default)]
+		like_count: u64,
+		#[serde(default)]
+		repost_count: u64,
+		#[serde(default)]
+		reply_count: u64,
+	}
+	#[derive(Deserialize)]
+	struct Posts {
+		posts: Vec<Post>,
+	}
+	let text = call(config, secrets, |client| {
+		client
+			.get(xrpc(&config.instance, "app.bsky.feed.getPosts"))
+			.query(&[("uris", uri)])
+	})?;
+	let posts = serde_json::from_str::<Posts>(&text)?.posts;
+	Ok(posts.into_iter().next().map(|post| Engagement {
+		favourites: post.like_count,
+		reblogs: post.repost_count,
+		replies: post.reply_count,
+		checked: Utc::now(),
+	}))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{sync::Mutex, thread};
+
+	use super::*;
+	use crate::{api::mock, data::config::Backend};
+
+	fn config(instance: &str) -> Config {
+		toml::from_str(&format!(
+			"instance = \"{instance}\"\n\
+			backend = \"bluesky\"\n\
+			bluesky.handle = \"bot.test\"\n\
+			bluesky.password = \"hunter2\"\n\
+			cache.enable = false\n"
+		))
+		.unwrap()
+	}
+
+	#[test]
+	fn logs_in_refreshes_and_posts() {
+		let (url, server) = mock::serve(
+			"application/json",
+			vec![
+				(
+					200,
+					r#"{"did":"did:plc:bot","handle":"bot.test",
+					"accessJwt":"access1","refreshJwt":"refresh1"}"#,
+				),
+				(
+					400,
+					r#"{"error":"ExpiredToken","message":"Expired"}"#,
+				),
+				(
+					200,
+					r#"{"did":"did:plc:bot","handle":"bot.test",
+					"accessJwt":"access2","refreshJwt":"refresh2"}"#,
+				),
+				(
+					200,
+					r#"{"uri":"at://did:plc:bot/app.bsky.feed.post/3abc",
+					"cid":"bafy"}"#,
+				),
+			],
+		);
+		let config = config(&url);
+
+		let session = login(&config).unwrap();
+		assert_eq!(session.access_jwt, "access1");
+		let secrets = Secrets {
+			backend: Backend::Bluesky,
+			client: None,
+			token: String::new(),
+			scopes: None,
+			session: Some(Mutex::new(session)),
+			new: true,
+		};
+		let body =
+			body(&config, &secrets, "An invention", &[]).unwrap();
+		let response = create(&config, &secrets, &body).unwrap();
+		assert_eq!(
+			response.id,
+			"at://did:plc:bot/app.bsky.feed.post/3abc"
+		);
+		assert_eq!(
+			response.url.as_deref(),
+			Some("https://bsky.app/profile/bot.test/post/3abc")
+		);
+		let session = secrets.session.unwrap().into_inner().unwrap();
+		assert_eq!(session.refresh_jwt, "refresh2");
+
+		let seen = server.join().unwrap();
+		let lines =
+			seen.iter().map(|s| s.line.as_str()).collect::<Vec<_>>();
+		assert_eq!(lines, [
+			"POST /xrpc/com.atproto.server.createSession HTTP/1.1",
+			"POST /xrpc/com.atproto.repo.createRecord HTTP/1.1",
+			"POST /xrpc/com.atproto.server.refreshSession HTTP/1.1",
+			"POST /xrpc/com.atproto.repo.createRecord HTTP/1.1",
+		]);
+		let login =
+			serde_json::from_str::<Value>(&seen[0].body).unwrap();
+		assert_eq!(login["identifier"], "bot.test");
+		assert_eq!(login["password"], "hunter2");
+		let authorization = seen
+			.iter()
+			.map(|s| s.header("Authorization"))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			authorization,
+			[
+				None,
+				Some("Bearer access1"),
+				Some("Bearer refresh1"),
+				Some("Bearer access2"),
+			]
+		);
+		// the post is sent again as it was, with the same record key
+		assert_eq!(seen[1].body, seen[3].body);
+		let put =
+			serde_json::from_str::<Value>(&seen[3].body).unwrap();
+		assert_eq!(put["repo"], "did:plc:bot");
+		assert_eq!(put["collection"], COLLECTION);
+		assert_eq!(put["record"]["text"], "An invention");
+		assert_eq!(put["rkey"].as_str().unwrap().len(), 13);
+	}
+
+	#[test]
+	fn posts_that_were_already_made() {
+		let (url, server) = mock::serve(
+			"application/json",
+			vec![
+				(
+					400,
+					r#"{"error":"InvalidRequest",
+					"message":"Record already exists"}"#,
+				),
+				(
+					200,
+					r#"{"uri":"at://did:plc:bot/app.bsky.feed.post/3abc",
+					"cid":"bafy","value":{}}"#,
+				),
+			],
+		);
+		let config = config(&url);
+		let secrets = Secrets {
+			backend: Backend::Bluesky,
+			client: None,
+			token: String::new(),
+			scopes: None,
+			session: Some(Mutex::new(Session {
+				did: String::from("did:plc:bot"),
+				handle: String::from("bot.test"),
+				access_jwt: String::from("access"),
+				refresh_jwt: String::from("refresh"),
+			})),
+			new: false,
+		};
+		let body =
+			body(&config, &secrets, "An invention", &[]).unwrap();
+		let response = create(&config, &secrets, &body).unwrap();
+		assert_eq!(
+			response.id,
+			"at://did:plc:bot/app.bsky.feed.post/3abc"
+		);
+
+		let seen = server.join().unwrap();
+		let rkey = serde_json::from_str::<Value>(&body).unwrap()
+			["rkey"]
+			.as_str()
+			.unwrap()
+			.to_owned();
+		assert_eq!(
+			seen[1].line,
+			format!(
+				"GET /xrpc/com.atproto.repo.getRecord\
+				?repo=did%3Aplc%3Abot&collection=app.bsky.feed.post\
+				&rkey={rkey} HTTP/1.1"
+			)
+		);
+	}
+
+	#[test]
+	fn facet_offsets() {
+		let config = config("http://127.0.0.1:1");
+		// the é takes two bytes, so offsets are in bytes, not chars
+		let text = "Café #inventions, see https://example.com/a_b.";
+		let facets = facets(&config, text);
+		assert_eq!(facets.len(), 2);
+		let link = &facets[0];
+		let start = text.find("https").unwrap();
+		assert_eq!(link["index"]["byteStart"], start);
+		assert_eq!(link["index"]["byteEnd"], text.len() - 1);
+		assert_eq!(
+			link["features"][0]["uri"],
+			"https://example.com/a_b"
+		);
+		let tag = &facets[1];
+		assert_eq!(tag["index"]["byteStart"], 6);
+		assert_eq!(tag["index"]["byteEnd"], 17);
+		assert_eq!(tag["features"][0]["tag"], "inventions");
+	}
+
+	#[test]
+	fn numeric_tags_are_skipped() {
+		let config = config("http://127.0.0.1:1");
+		let facets = facets(&config, "#2024 #1st #wow!");
+		let tags = facets
+			.iter()
+			.map(|f| f["features"][0]["tag"].as_str().unwrap())
+			.collect::<Vec<_>>();
+		assert_eq!(tags, ["1st", "wow"]);
+	}
+
+	#[test]
+	fn tids() {
+		let first = tid();
+		assert_eq!(first.len(), 13);
+		assert!(first.bytes().all(|c| TID_ALPHABET.contains(&c)));
+		// the top bit is always zero
+		assert!(b"234567abcdefghij".contains(&first.as_bytes()[0]));
+		thread::sleep(std::time::Duration::from_millis(2));
+		assert!(tid() > first, "later keys sort after earlier ones");
+	}
+}
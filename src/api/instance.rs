@@ -0,0 +1,96 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+/// How long statuses on an instance can be.
+pub struct Limits {
+	pub max_characters: usize,
+	/// How many characters every link counts as, however long it is.
+	pub characters_reserved_per_url: Option<usize>,
+}
+
+/// Newer instances put their limits in `configuration.statuses`,
+/// older ones, and Pleroma, only have `max_toot_chars`.
+fn parse(instance: &Value) -> Option<Limits> {
+	let number = |value: Option<&Value>| {
+		value.and_then(Value::as_u64).map(|n| n as usize)
+	};
+	let statuses = instance.pointer("/configuration/statuses");
+	let max_characters = number(
+		statuses.and_then(|statuses| statuses.get("max_characters")),
+	)
+	.or_else(|| number(instance.get("max_toot_chars")))?;
+	Some(Limits {
+		max_characters,
+		characters_reserved_per_url: number(statuses.and_then(
+			|statuses| statuses.get("characters_reserved_per_url"),
+		)),
+	})
+}
+
+// GET /api/v2/instance
+// GET /api/v1/instance
+/// Fetches the instance's limits, from the newer API if it has it.
+/// Each is only asked once, since what was found out last time will
+/// do if neither answers.
+pub fn limits(instance: &str) -> Result<Limits> {
+	let mut last = None;
+	for path in ["/api/v2/instance", "/api/v1/instance"] {
+		// anything going wrong with the newer API falls through to
+		// the older one
+		let value = super::request::send_once(|client| {
+			client.get(super::url(instance, path))
+		})
+		.and_then(|response| {
+			let text = response.error_for_status()?.text()?;
+			Ok(serde_json::from_str::<Value>(&text)?)
+		});
+		match value {
+			Ok(value) => {
+				if let Some(limits) = parse(&value) {
+					return Ok(limits);
+				}
+				last = Some(anyhow!(
+					"{path} doesn't say how long statuses can be."
+				));
+			},
+			Err(e) => last = Some(e),
+		}
+	}
+	Err(last.unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::*;
+
+	#[test]
+	fn parses_limits() {
+		let v2 = json!({
+			"configuration": { "statuses": {
+				"max_characters": 1000,
+				"characters_reserved_per_url": 23,
+			}},
+		});
+		let limits = parse(&v2).unwrap();
+		assert_eq!(limits.max_characters, 1000);
+		assert_eq!(limits.characters_reserved_per_url, Some(23));
+
+		let pleroma = json!({ "max_toot_chars": 5000 });
+		let limits = parse(&pleroma).unwrap();
+		assert_eq!(limits.max_characters, 5000);
+		assert_eq!(limits.characters_reserved_per_url, None);
+
+		// the newer place wins when both are there
+		let both = json!({
+			"max_toot_chars": 5000,
+			"configuration": { "statuses": { "max_characters": 500 }},
+		});
+		assert_eq!(parse(&both).unwrap().max_characters, 500);
+
+		assert!(parse(&json!({ "title": "An instance" })).is_none());
+		let wrong = json!({ "max_toot_chars": "lots" });
+		assert!(parse(&wrong).is_none());
+	}
+}
@@ -0,0 +1,80 @@
+use std::{thread, time::Duration};
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::{
+	blocking::multipart::{Form, Part},
+	StatusCode,
+};
+use serde::Deserialize;
+
+use crate::data::secrets::Secrets;
+
+// How long to wait for the instance to finish processing an upload.
+const POLLS: u32 = 10;
+const POLL_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Deserialize)]
+struct Attachment {
+	id: String,
+	url: Option<String>,
+}
+
+// POST /api/v2/media
+/// Uploads a PNG with the given alt text, returning the id to attach it
+/// to a status with.
+pub fn upload(
+	instance: &str,
+	secrets: &Secrets,
+	image: Vec<u8>,
+	description: &str,
+) -> Result<String> {
+	log::info!("Uploading card");
+	// a form can only be sent once, so it's built again for each attempt
+	let form = || {
+		Form::new()
+			.part(
+				"file",
+				Part::bytes(image.clone())
+					.file_name("card.png")
+					.mime_str("image/png")
+					.expect("image/png is a valid MIME type."),
+			)
+			.text("description", description.to_owned())
+	};
+	let text = super::request::send(|client| {
+		client
+			.post(super::url(instance, "/api/v2/media"))
+			.multipart(form())
+			.header("Authorization", &secrets.token)
+	})?
+	.error_for_status()
+	.context("Failed to upload card.")?
+	.text()?;
+	let attachment = serde_json::from_str::<Attachment>(&text)?;
+	if attachment.url.is_none() {
+		wait(instance, secrets, &attachment.id)?;
+	}
+	Ok(attachment.id)
+}
+
+// GET /api/v1/media/:id
+/// Large uploads are processed asynchronously, and can't be attached to
+/// a status until they're done.
+fn wait(instance: &str, secrets: &Secrets, id: &str) -> Result<()> {
+	for _ in 0..POLLS {
+		thread::sleep(POLL_DELAY);
+		let response = super::request::send(|client| {
+			client
+				.get(super::url(
+					instance,
+					&format!("/api/v1/media/{id}"),
+				))
+				.header("Authorization", &secrets.token)
+		})?
+		.error_for_status()?;
+		if response.status() != StatusCode::PARTIAL_CONTENT {
+			return Ok(());
+		}
+	}
+	Err(anyhow!("Card {id} is still processing, giving up."))
+}
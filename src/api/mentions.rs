@@ -0,0 +1,411 @@
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Result};
+use rand::{distributions::Distribution, Rng, SeedableRng};
+use regex::Regex;
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+use super::post::{self, PostCfg, Reply};
+use crate::data::{
+	config::{Config, Visibility},
+	mentions::Mentions,
+	secrets::Secrets,
+};
+
+// The most notifications Mastodon will return at once.
+const PAGE: usize = 40;
+// Anything longer is probably not a name.
+const MAX_NAME: usize = 64;
+// How many more times a mention is tried if answering it fails.
+const RETRIES: u32 = 3;
+
+#[derive(Debug, Deserialize)]
+struct Account {
+	acct: String,
+	#[serde(default)]
+	bot: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Status {
+	id: String,
+	content: String,
+	visibility: Visibility,
+	account: Account,
+}
+
+#[derive(Debug, Deserialize)]
+struct Notification {
+	id: String,
+	status: Option<Status>,
+}
+
+// GET /api/v1/notifications
+/// Fetches up to `limit` mentions, newest first. With `min_id`, these
+/// are the ones immediately after it.
+fn page(
+	instance: &str,
+	secrets: &Secrets,
+	min_id: Option<&str>,
+	limit: usize,
+) -> Result<Vec<Notification>> {
+	let mut query = vec![
+		("types[]", String::from("mention")),
+		("limit", limit.to_string()),
+	];
+	if let Some(min_id) = min_id {
+		query.push(("min_id", min_id.to_owned()));
+	}
+	let text = super::request::send(|client| {
+		client
+			.get(super::url(instance, "/api/v1/notifications"))
+			.query(&query)
+			.header("Authorization", &secrets.token)
+	})?
+	.error_for_status()?
+	.text()?;
+	Ok(serde_json::from_str(&text)?)
+}
+
+/// Fetches every mention after `since`, oldest first.
+fn fetch(
+	instance: &str,
+	secrets: &Secrets,
+	since: &str,
+) -> Result<Vec<Notification>> {
+	let mut out = Vec::new();
+	let mut since = since.to_owned();
+	loop {
+		let mut page = page(instance, secrets, Some(&since), PAGE)?;
+		let done = page.len() < PAGE;
+		page.reverse();
+		if let Some(last) = page.last() {
+			since = last.id.clone();
+		}
+		out.extend(page);
+		if done {
+			return Ok(out);
+		}
+	}
+}
+
+/// Turns the HTML content of a status back into plain text.
+fn plain(html: &str) -> String {
+	static TAGS: OnceLock<Regex> = OnceLock::new();
+	let tags = TAGS.get_or_init(|| {
+		Regex::new(r"<br ?/?>|</p>|<[^>]*>").unwrap()
+	});
+	tags.replace_all(html, |caps: &regex::Captures| match &caps[0] {
+		"</p>" | "<br>" | "<br/>" | "<br />" => "\n",
+		_ => "",
+	})
+	.replace("&lt;", "<")
+	.replace("&gt;", ">")
+	.replace("&quot;", "\"")
+	.replace("&#39;", "'")
+	.replace("&amp;", "&")
+}
+
+/// Finds who to invent something for, in a mention like "invent Alice".
+fn request(text: &str) -> Option<String> {
+	static INVENT: OnceLock<Regex> = OnceLock::new();
+	let invent = INVENT.get_or_init(|| {
+		Regex::new(r"(?im)\binvent\s+(.+)$").unwrap()
+	});
+	// don't let the bot be used to mention other people
+	let name = invent.captures(text)?[1]
+		.replace('@', "")
+		.trim()
+		.trim_end_matches(['.', '!', '?', ','])
+		.trim()
+		.to_owned();
+	match name.is_empty() || name.chars().count() > MAX_NAME {
+		true => None,
+		false => Some(name),
+	}
+}
+
+fn matches(entry: &str, acct: &str) -> bool {
+	let entry = entry.trim_start_matches('@');
+	match entry.strip_prefix("*@") {
+		Some(domain) => acct
+			.split_once('@')
+			.is_some_and(|(_, d)| d.eq_ignore_ascii_case(domain)),
+		None => entry.eq_ignore_ascii_case(acct),
+	}
+}
+
+fn is_allowed(config: &Config, acct: &str) -> bool {
+	let mentions = &config.mentions;
+	!mentions.deny.iter().any(|entry| matches(entry, acct))
+		&& (mentions.allow.is_empty()
+			|| mentions
+				.allow
+				.iter()
+				.any(|entry| matches(entry, acct)))
+}
+
+fn answer<T, D>(
+	cfg: &mut PostCfg<T, D>,
+	mentions: &mut Mentions,
+	status: &Status,
+) -> Result<()>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	let config = cfg.config;
+	let acct = &status.account.acct;
+	if status.account.bot && !config.mentions.bots {
+		log::info!("Ignoring mention from bot {acct}.");
+		return Ok(());
+	}
+	if !is_allowed(config, acct) {
+		log::info!(
+			"Ignoring mention from {acct}, who isn't allowed."
+		);
+		return Ok(());
+	}
+	let Some(name) = request(&plain(&status.content)) else {
+		log::debug!("Mention from {acct} isn't asking for anything.");
+		return Ok(());
+	};
+	if let Some(pattern) = cfg.filter.check(&name) {
+		log::warn!(
+			"Ignoring {acct} asking for {name:?}, which matched the \
+			blocklist entry {pattern}."
+		);
+		return Ok(());
+	}
+	if mentions.is_limited(config, acct) {
+		log::info!("Ignoring {acct}, who has asked too often.");
+		return Ok(());
+	}
+	post::reply(
+		cfg,
+		&name,
+		&Reply {
+			id: &status.id,
+			acct,
+			visibility: status.visibility,
+		},
+	)?;
+	mentions.replied(acct);
+	Ok(())
+}
+
+/// Whether an error might not happen again if the reply is tried
+/// later, because it didn't reach the instance or the instance was
+/// having trouble. Anything else, like the instance turning the reply
+/// down or no acceptable post being found, will just happen again.
+fn transient(e: &anyhow::Error) -> bool {
+	e.chain()
+		.find_map(|e| e.downcast_ref::<reqwest::Error>())
+		.is_some_and(|e| match e.status() {
+			Some(status) => {
+				status.is_server_error()
+					|| status == StatusCode::TOO_MANY_REQUESTS
+			},
+			None => true,
+		})
+}
+
+/// Answers each mention in turn, marking them as seen as it goes. If
+/// one fails in a way that might not happen again, it and everything
+/// after it are left until next time, up to `RETRIES` times, so
+/// nothing is lost. Any other failure is given up on straight away.
+fn work_through(
+	mentions: &mut Mentions,
+	notifications: Vec<Notification>,
+	mut answer: impl FnMut(&mut Mentions, &Status) -> Result<()>,
+) -> Result<()> {
+	for notification in notifications {
+		if let Some(status) = &notification.status {
+			if let Err(e) = answer(mentions, status) {
+				let id = &notification.id;
+				if transient(&e) && mentions.failures < RETRIES {
+					mentions.failures += 1;
+					log::error!(
+						"{:?}",
+						e.context(format!(
+							"Failed to answer mention {id}, will try \
+							again later."
+						))
+					);
+					return mentions.dump();
+				}
+				log::error!(
+					"{:?}",
+					e.context(format!(
+						"Failed to answer mention {id}, giving up on \
+						it."
+					))
+				);
+			}
+		}
+		mentions.seen(notification.id);
+		mentions.dump()?;
+	}
+	Ok(())
+}
+
+/// Replies to any mentions that have arrived since last time.
+pub fn check<T, D>(
+	cfg: &mut PostCfg<T, D>,
+	mentions: &mut Mentions,
+) -> Result<()>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	let secrets = cfg.secrets.ok_or(anyhow!("Not authorised."))?;
+	let instance = &cfg.config.instance;
+	let Some(since) = mentions.last_seen.clone() else {
+		// Nothing has been seen yet, so rather than answering every
+		// mention the bot has ever had, start from the newest.
+		let newest = page(instance, secrets, None, 1)?;
+		log::info!(
+			"Skipping mentions from before the bot was started."
+		);
+		mentions.last_seen = Some(
+			newest
+				.first()
+				.map_or_else(|| String::from("0"), |n| n.id.clone()),
+		);
+		return mentions.dump();
+	};
+	let notifications = fetch(instance, secrets, &since)?;
+	work_through(mentions, notifications, |mentions, status| {
+		answer(cfg, mentions, status)
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn plain_text() {
+		assert_eq!(
+			plain(
+				"<p><span><a href=\"x\">@bot</a></span> invent Tom \
+				&amp; Jerry</p><p>please<br />now</p>"
+			),
+			"@bot invent Tom & Jerry\nplease\nnow\n"
+		);
+	}
+
+	#[test]
+	fn requests() {
+		assert_eq!(
+			request("@bot invent Ada Lovelace!").as_deref(),
+			Some("Ada Lovelace")
+		);
+		assert_eq!(
+			request("@bot please\nINVENT  Grace Hopper.\nthanks")
+				.as_deref(),
+			Some("Grace Hopper")
+		);
+		// nobody else gets mentioned by the reply
+		assert_eq!(
+			request("@bot invent @alice@example.com").as_deref(),
+			Some("aliceexample.com")
+		);
+		assert_eq!(request("@bot hello"), None);
+		assert_eq!(request("@bot invent ?!"), None);
+		let long =
+			format!("@bot invent {}", "a".repeat(MAX_NAME + 1));
+		assert_eq!(request(&long), None);
+	}
+
+	#[test]
+	fn account_patterns() {
+		assert!(matches("alice@example.com", "Alice@Example.com"));
+		assert!(matches("@alice", "alice"));
+		assert!(!matches("alice", "alice@example.com"));
+		assert!(matches("*@example.com", "bob@example.com"));
+		assert!(!matches("*@example.com", "bob@example.org"));
+		assert!(!matches("*@example.com", "bob"));
+	}
+
+	fn mention(id: &str) -> Notification {
+		Notification {
+			id: id.to_owned(),
+			status: Some(Status {
+				id: id.to_owned(),
+				content: String::from("@bot invent Alice"),
+				visibility: Visibility::Public,
+				account: Account {
+					acct: String::from("alice"),
+					bot: false,
+				},
+			}),
+		}
+	}
+
+	#[test]
+	fn failing_mentions_dont_block_the_rest() {
+		let dir = std::env::temp_dir().join(format!(
+			"inventor_bot_mentions_{}",
+			std::process::id()
+		));
+		let config = toml::from_str::<Config>(&format!(
+			"instance = \"example.com\"\ncache.path = {:?}\n",
+			dir
+		))
+		.unwrap();
+		let mut mentions = Mentions::load(&config).unwrap();
+		// nothing's listening here, so sending anything fails
+		let unreachable = || {
+			reqwest::blocking::get("http://127.0.0.1:1")
+				.map(|_| ())
+				.map_err(anyhow::Error::from)
+		};
+
+		let mut answered = Vec::new();
+		work_through(
+			&mut mentions,
+			vec![mention("1"), mention("2")],
+			|_, status| {
+				answered.push(status.id.clone());
+				match status.id.as_str() {
+					"1" => {
+						Err(anyhow!("Could not come up with a post."))
+					},
+					_ => Ok(()),
+				}
+			},
+		)
+		.unwrap();
+		assert_eq!(answered, ["1", "2"]);
+		assert_eq!(mentions.last_seen.as_deref(), Some("2"));
+
+		let mut answered = Vec::new();
+		for _ in 0..=RETRIES {
+			work_through(
+				&mut mentions,
+				vec![mention("3"), mention("4")],
+				|_, status| {
+					answered.push(status.id.clone());
+					match status.id.as_str() {
+						"3" => unreachable(),
+						_ => Ok(()),
+					}
+				},
+			)
+			.unwrap();
+		}
+		// it's tried until it runs out of retries, then skipped
+		let mut expected = vec!["3"; RETRIES as usize + 1];
+		expected.push("4");
+		assert_eq!(answered, expected);
+		assert_eq!(mentions.last_seen.as_deref(), Some("4"));
+		assert_eq!(mentions.failures, 0);
+
+		// and that's all remembered
+		let mentions = Mentions::load(&config).unwrap();
+		assert_eq!(mentions.last_seen.as_deref(), Some("4"));
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+}
@@ -0,0 +1,407 @@
+// Misskey, and forks of it like Firefish and Sharkey, have their own
+// API rather than Mastodon's. Every endpoint takes a JSON body, and is
+// POSTed to, even the ones that only read.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::blocking::multipart::{Form, Part};
+use serde::{Deserialize, Serialize};
+
+use super::{
+	polls::{Choice, Poll},
+	post::{PollResponse, Response},
+};
+use crate::data::{
+	config::{Config, Visibility},
+	history::Engagement,
+	secrets::Secrets,
+};
+
+/// The permissions asked for when logging in, the equivalent of OAuth
+/// scopes.
+pub const PERMISSIONS: &[&str] =
+	&["read:account", "write:notes", "write:drive"];
+
+/// Misskey calls unlisted posts "home", since they only show up on
+/// the home timelines of followers, and direct posts "specified".
+pub fn visibility(visibility: Visibility) -> &'static str {
+	match visibility {
+		Visibility::Public => "public",
+		Visibility::Unlisted => "home",
+		Visibility::Private => "followers",
+		Visibility::Direct => "specified",
+	}
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotePoll<'a> {
+	pub choices: &'a [String],
+	pub multiple: bool,
+	/// In milliseconds.
+	pub expired_after: u64,
+}
+
+// POST /api/notes/create
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Note<'a> {
+	pub text: &'a str,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub cw: Option<&'a str>,
+	pub visibility: &'static str,
+	#[serde(skip_serializing_if = "<[_]>::is_empty")]
+	pub file_ids: &'a [String],
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub reply_id: Option<&'a str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub poll: Option<NotePoll<'a>>,
+}
+
+fn call(
+	instance: &str,
+	token: &str,
+	endpoint: &str,
+	body: String,
+) -> Result<reqwest::blocking::Response> {
+	super::request::send(|client| {
+		client
+			.post(super::url(instance, &format!("/api/{endpoint}")))
+			.header("Content-Type", "application/json")
+			.header("Authorization", token)
+			.body(body.clone())
+	})
+}
+
+fn post(
+	instance: &str,
+	token: &str,
+	endpoint: &str,
+	body: String,
+) -> Result<String> {
+	Ok(call(instance, token, endpoint, body)?
+		.error_for_status()?
+		.text()?)
+}
+
+/// Calls an endpoint about one note, returning `None` if the note
+/// has been deleted.
+fn note(
+	instance: &str,
+	token: &str,
+	endpoint: &str,
+	id: &str,
+) -> Result<Option<String>> {
+	#[derive(Deserialize)]
+	struct Error {
+		code: String,
+	}
+	#[derive(Deserialize)]
+	struct Failed {
+		error: Error,
+	}
+	let body = serde_json::json!({ "noteId": id }).to_string();
+	let response = call(instance, token, endpoint, body)?;
+	let failed = response.error_for_status_ref().err();
+	let text = response.text()?;
+	let Some(e) = failed else {
+		return Ok(Some(text));
+	};
+	match serde_json::from_str::<Failed>(&text) {
+		Ok(failed) if failed.error.code == "NO_SUCH_NOTE" => Ok(None),
+		_ => Err(e.into()),
+	}
+}
+
+/// Misskey wants a UUID to identify the login session.
+fn session() -> String {
+	let hex = format!("{:032x}", rand::thread_rng().gen::<u128>());
+	format!(
+		"{}-{}-{}-{}-{}",
+		&hex[..8],
+		&hex[8..12],
+		&hex[12..16],
+		&hex[16..20],
+		&hex[20..]
+	)
+}
+
+// GET /miauth/:session
+// POST /api/miauth/:session/check
+/// Logs in with MiAuth, which is much simpler than OAuth: the user
+/// approves a session in their browser, and then the bot asks for the
+/// token that was made for it.
+pub fn login(config: &Config) -> Result<String> {
+	#[derive(Serialize)]
+	struct Request<'a> {
+		name: &'a str,
+		callback: &'a str,
+		permission: &'a str,
+	}
+	#[derive(Deserialize)]
+	struct Response {
+		ok: bool,
+		token: Option<String>,
+	}
+	log::info!("Authorising user");
+	let session = session();
+	let params = Request {
+		name: &config.client,
+		callback: &format!("http://127.0.0.1:{}", config.port),
+		permission: &PERMISSIONS.join(","),
+	};
+	let url = format!(
+		"{}?{}",
+		super::url(&config.instance, &format!("/miauth/{session}")),
+		serde_qs::to_string(&params)?
+	);
+	super::auth::callback(config, &url)?;
+
+	log::info!("Requesting a new token");
+	let text = super::request::send(|client| {
+		client
+			.post(super::url(
+				&config.instance,
+				&format!("/api/miauth/{session}/check"),
+			))
+			.header("Content-Type", "application/json")
+			.body("{}")
+	})?
+	.error_for_status()?
+	.text()?;
+	match serde_json::from_str::<Response>(&text)? {
+		Response {
+			ok: true,
+			token: Some(token),
+		} => Ok(format!("Bearer {token}")),
+		_ => Err(anyhow!("The login was not approved.")),
+	}
+}
+
+// POST /api/i
+pub fn verify(token: &str, instance: &str) -> Result<()> {
+	log::info!("Verifying authorisation token.");
+	post(instance, token, "i", String::from("{}"))
+		.context("Authorisation token failed verification.")?;
+	Ok(())
+}
+
+// POST /api/drive/files/create
+/// Uploads a PNG to the drive, with the given alt text, returning the
+/// id to attach it to a note with. Misskey marks files as sensitive
+/// rather than notes.
+pub fn upload(
+	config: &Config,
+	secrets: &Secrets,
+	image: Vec<u8>,
+	description: &str,
+) -> Result<String> {
+	#[derive(Deserialize)]
+	struct File {
+		id: String,
+	}
+	log::info!("Uploading card");
+	let form = || {
+		Form::new()
+			.part(
+				"file",
+				Part::bytes(image.clone())
+					.file_name("card.png")
+					.mime_str("image/png")
+					.expect("image/png is a valid MIME type."),
+			)
+			.text("comment", description.to_owned())
+			.text("isSensitive", config.sensitive.to_string())
+	};
+	let text = super::request::send(|client| {
+		client
+			.post(super::url(
+				&config.instance,
+				"/api/drive/files/create",
+			))
+			.multipart(form())
+			.header("Authorization", &secrets.token)
+	})?
+	.error_for_status()
+	.context("Failed to upload card.")?
+	.text()?;
+	Ok(serde_json::from_str::<File>(&text)?.id)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NoteResponse {
+	id: String,
+	poll: Option<PollState>,
+	#[serde(default)]
+	reactions: BTreeMap<String, u64>,
+	#[serde(default)]
+	renote_count: u64,
+	#[serde(default)]
+	replies_count: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PollState {
+	expires_at: Option<DateTime<Utc>>,
+	choices: Vec<ChoiceState>,
+}
+
+#[derive(Deserialize)]
+struct ChoiceState {
+	text: String,
+	votes: u64,
+}
+
+/// Makes a note from a body built from a [`Note`]. Polls belong to
+/// notes rather than having ids of their own, so the note's id is
+/// used for the poll too.
+pub fn create(
+	instance: &str,
+	secrets: &Secrets,
+	body: &str,
+) -> Result<Response> {
+	#[derive(Deserialize)]
+	#[serde(rename_all = "camelCase")]
+	struct Created {
+		created_note: NoteResponse,
+	}
+	// without an idempotency key, a note that's sent again might be
+	// made twice, so it's only sent once
+	let text = super::request::send_once(|client| {
+		client
+			.post(super::url(instance, "/api/notes/create"))
+			.header("Content-Type", "application/json")
+			.header("Authorization", &secrets.token)
+			.body(body.to_owned())
+	})?
+	.error_for_status()?
+	.text()?;
+	let note = serde_json::from_str::<Created>(&text)?.created_note;
+	Ok(Response {
+		url: Some(super::url(
+			instance,
+			&format!("/notes/{}", note.id),
+		)),
+		poll: note.poll.map(|poll| PollResponse {
+			id: note.id.clone(),
+			expires_at: poll.expires_at,
+		}),
+		id: note.id,
+	})
+}
+
+// POST /api/notes/show
+pub fn poll(
+	instance: &str,
+	secrets: &Secrets,
+	id: &str,
+) -> Result<Poll> {
+	let body = serde_json::json!({ "noteId": id }).to_string();
+	let text = post(instance, &secrets.token, "notes/show", body)?;
+	let poll = serde_json::from_str::<NoteResponse>(&text)?
+		.poll
+		.ok_or(anyhow!("Note {id} doesn't have a poll."))?;
+	Ok(Poll {
+		expired: poll.expires_at.is_some_and(|at| at <= Utc::now()),
+		options: poll
+			.choices
+			.into_iter()
+			.map(|choice| Choice {
+				title: choice.text,
+				votes_count: Some(choice.votes),
+			})
+			.collect(),
+	})
+}
+
+// POST /api/notes/show
+/// How people have interacted with a note, or `None` if it's gone.
+/// Reactions are Misskey's equivalent of favourites, and renotes of
+/// reblogs.
+pub fn engagement(
+	instance: &str,
+	secrets: &Secrets,
+	id: &str,
+) -> Result<Option<Engagement>> {
+	let shown = note(instance, &secrets.token, "notes/show", id)?;
+	let Some(text) = shown else {
+		return Ok(None);
+	};
+	let note = serde_json::from_str::<NoteResponse>(&text)?;
+	Ok(Some(Engagement {
+		favourites: note.reactions.values().sum(),
+		reblogs: note.renote_count,
+		replies: note.replies_count,
+		checked: Utc::now(),
+	}))
+}
+
+// POST /api/notes/delete
+/// Deletes a note, which is fine if it's already gone.
+pub fn delete(
+	instance: &str,
+	secrets: &Secrets,
+	id: &str,
+) -> Result<()> {
+	note(instance, &secrets.token, "notes/delete", id)?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{api::mock, data::config::Backend};
+
+	#[test]
+	fn engagement_of_missing_notes() {
+		let (url, server) = mock::serve(
+			"application/json",
+			vec![
+				(
+					200,
+					r#"{"id":"n1","reactions":{"👍":2,":blob:":1},
+					"renoteCount":4,"repliesCount":1}"#,
+				),
+				(
+					400,
+					r#"{"error":{"message":"No such note.",
+					"code":"NO_SUCH_NOTE","id":"x"}}"#,
+				),
+				(
+					400,
+					r#"{"error":{"message":"Bad.",
+					"code":"INVALID_PARAM","id":"y"}}"#,
+				),
+			],
+		);
+		let secrets = Secrets {
+			backend: Backend::Misskey,
+			client: None,
+			token: String::from("Bearer token"),
+			scopes: None,
+			session: None,
+			new: false,
+		};
+		let found =
+			engagement(&url, &secrets, "n1").unwrap().unwrap();
+		assert_eq!(
+			(found.favourites, found.reblogs, found.replies),
+			(3, 4, 1)
+		);
+		assert!(engagement(&url, &secrets, "n2").unwrap().is_none());
+		assert!(engagement(&url, &secrets, "n3").is_err());
+
+		let seen = server.join().unwrap();
+		assert_eq!(seen[0].line, "POST /api/notes/show HTTP/1.1");
+		assert_eq!(
+			seen[0].header("Authorization"),
+			Some("Bearer token")
+		);
+		assert_eq!(seen[1].body, r#"{"noteId":"n2"}"#);
+	}
+}
@@ -0,0 +1,87 @@
+// A stand-in for an instance in tests, which answers each request it
+// gets with the next of a list of canned responses.
+
+use std::{
+	io::{BufRead, BufReader, Read, Write},
+	net::{TcpListener, TcpStream},
+	thread::{self, JoinHandle},
+};
+
+use reqwest::blocking::Client;
+
+/// A request as the server saw it.
+pub struct Seen {
+	pub line: String,
+	pub headers: Vec<(String, String)>,
+	pub body: String,
+}
+
+impl Seen {
+	/// The value of a header, whatever case its name is in.
+	pub fn header(&self, name: &str) -> Option<&str> {
+		self.headers
+			.iter()
+			.find(|(n, _)| n.eq_ignore_ascii_case(name))
+			.map(|(_, value)| value.as_str())
+	}
+}
+
+/// Reads a whole request, however many packets it comes in.
+fn read(reader: &mut BufReader<TcpStream>) -> Seen {
+	let mut line = String::new();
+	reader.read_line(&mut line).unwrap();
+	let mut headers = Vec::new();
+	loop {
+		let mut header = String::new();
+		reader.read_line(&mut header).unwrap();
+		let header = header.trim_end();
+		if header.is_empty() {
+			break;
+		}
+		let (name, value) = header.split_once(':').unwrap();
+		headers.push((name.to_owned(), value.trim().to_owned()));
+	}
+	let mut seen = Seen {
+		line: line.trim_end().to_owned(),
+		headers,
+		body: String::new(),
+	};
+	let length = seen
+		.header("Content-Length")
+		.map_or(0, |length| length.parse().unwrap());
+	let mut body = vec![0; length];
+	reader.read_exact(&mut body).unwrap();
+	seen.body = String::from_utf8(body).unwrap();
+	seen
+}
+
+/// Answers one request for each response, in order. Returns the
+/// server's address, and a handle which gives what was asked once
+/// every response has been sent.
+pub fn serve(
+	content_type: &'static str,
+	responses: Vec<(u16, &'static str)>,
+) -> (String, JoinHandle<Vec<Seen>>) {
+	let _ = super::RQ_CLIENT.set(Client::new());
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let url = format!("http://{}", listener.local_addr().unwrap());
+	let handle = thread::spawn(move || {
+		let mut seen = Vec::new();
+		for (status, body) in responses {
+			let (socket, _) = listener.accept().unwrap();
+			let mut reader = BufReader::new(socket);
+			seen.push(read(&mut reader));
+			write!(
+				reader.get_mut(),
+				"HTTP/1.1 {status} X\r\n\
+				Content-Type: {content_type}\r\n\
+				Content-Length: {}\r\n\
+				Connection: close\r\n\r\n{body}",
+				body.len()
+			)
+			.unwrap();
+		}
+		seen
+	});
+	(url, handle)
+}
@@ -5,8 +5,24 @@ use reqwest::blocking::Client;
 use serde::Deserialize;
 
 pub mod auth;
-pub mod words;
+pub mod bluesky;
+mod instance;
+pub mod media;
+pub mod mentions;
+pub mod misskey;
+pub mod nodeinfo;
+pub mod polls;
 pub mod post;
+pub mod profile;
+pub mod prune;
+mod request;
+pub mod schedule;
+pub mod stats;
+pub mod streaming;
+pub mod words;
+
+#[cfg(test)]
+mod mock;
 
 #[derive(Deserialize)]
 struct Error<'a> {
@@ -16,6 +32,16 @@ struct Error<'a> {
 
 static RQ_CLIENT: OnceLock<Client> = OnceLock::new();
 
+/// The instance is usually just a domain, but it can be given with a
+/// scheme too, e.g. `http://localhost:3000` to test against a local
+/// server.
+pub fn url(instance: &str, path: &str) -> String {
+	match instance.contains("://") {
+		true => format!("{}{path}", instance.trim_end_matches('/')),
+		false => format!("https://{instance}{path}"),
+	}
+}
+
 pub fn init() -> Result<()> {
 	let client = Client::builder()
 		.user_agent("inventor_bot")
@@ -0,0 +1,222 @@
+// NodeInfo is how instances say what software they run, which the bot
+// uses to work out what it can send them. Every instance finds its
+// NodeInfo documents the same way, whatever its API is like.
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::instance;
+use crate::data::{
+	config::{Backend, Config},
+	instance::{Features, Instance, Software},
+};
+
+/// The `rel` of links to NodeInfo documents, followed by the version
+/// of the schema.
+const SCHEMA: &str = "http://nodeinfo.diaspora.software/ns/schema/";
+
+/// Software with Misskey's API rather than Mastodon's.
+const MISSKEY: &[&str] = &[
+	"misskey",
+	"firefish",
+	"calckey",
+	"foundkey",
+	"sharkey",
+	"iceshrimp",
+	"cherrypick",
+	"catodon",
+];
+
+// If the instance doesn't answer, what's known of it already is good
+// enough, so there's no point retrying.
+fn get(url: &str) -> Result<String> {
+	Ok(super::request::send_once(|client| client.get(url))?
+		.error_for_status()?
+		.text()?)
+}
+
+// GET /.well-known/nodeinfo
+/// Finds the newest NodeInfo document the instance has, returning the
+/// software it names, and its metadata, which is different for each.
+fn fetch(instance: &str) -> Result<(Software, Value)> {
+	#[derive(Deserialize)]
+	struct Link {
+		rel: String,
+		href: String,
+	}
+	#[derive(Deserialize)]
+	struct Links {
+		links: Vec<Link>,
+	}
+	#[derive(Deserialize)]
+	struct NodeInfo {
+		software: Software,
+		#[serde(default)]
+		metadata: Value,
+	}
+	let text = get(&super::url(instance, "/.well-known/nodeinfo"))?;
+	let link = serde_json::from_str::<Links>(&text)?
+		.links
+		.into_iter()
+		.filter(|link| link.rel.starts_with(SCHEMA))
+		.max_by(|a, b| a.rel.cmp(&b.rel))
+		.ok_or(anyhow!("The instance has no NodeInfo."))?;
+	let text = get(&link.href)?;
+	let mut info = serde_json::from_str::<NodeInfo>(&text)
+		.context("Failed to parse NodeInfo.")?;
+	info.software.name = info.software.name.to_lowercase();
+	Ok((info.software, info.metadata))
+}
+
+/// Works out what the instance can do from the software it runs.
+/// Pleroma and Akkoma list their features, for everything else it's
+/// down to what that software is known to do.
+fn features(software: &Software, metadata: &Value) -> Features {
+	let strings = |key: &str| {
+		metadata
+			.get(key)
+			.and_then(Value::as_array)
+			.into_iter()
+			.flatten()
+			.filter_map(Value::as_str)
+			.map(str::to_owned)
+			.collect::<Vec<_>>()
+	};
+	match software.name.as_str() {
+		"pleroma" | "akkoma" => {
+			let listed = strings("features");
+			let formats = strings("postFormats");
+			Features {
+				content_types: match formats.is_empty() {
+					true => Features::default().content_types,
+					false => formats,
+				},
+				max_length: None,
+				url_length: None,
+				graphemes: false,
+				polls: listed.is_empty()
+					|| listed.iter().any(|f| f == "polls"),
+			}
+		},
+		"gotosocial" => Features {
+			content_types: vec![
+				String::from("text/plain"),
+				String::from("text/markdown"),
+			],
+			..Features::default()
+		},
+		name if MISSKEY.contains(&name) => Features {
+			content_types: Vec::new(),
+			max_length: metadata
+				.get("maxNoteTextLength")
+				.and_then(Value::as_u64)
+				.map(|max| max as usize),
+			url_length: None,
+			graphemes: false,
+			polls: true,
+		},
+		_ => Features::default(),
+	}
+}
+
+/// Warns about anything in the config the instance can't do.
+fn check(config: &Config, software: &Software, features: &Features) {
+	let name = &software.name;
+	let misskey = MISSKEY.contains(&name.as_str());
+	match (config.backend, misskey) {
+		(Backend::Mastodon, true) => log::warn!(
+			"{} runs {name}, which has its own API, set \
+			`backend = \"misskey\"` to post to it.",
+			config.instance
+		),
+		(Backend::Misskey, false) => log::warn!(
+			"{} runs {name} rather than Misskey, leave out `backend` \
+			to use Mastodon's API instead.",
+			config.instance
+		),
+		_ => {},
+	}
+	if config.poll.enable && !features.polls {
+		log::warn!(
+			"{name} doesn't have polls, so none will be made."
+		);
+	}
+}
+
+/// Fills in how long posts can be from the instance's own API, or
+/// from what it said last time if it can't be asked.
+fn limit(
+	config: &Config,
+	features: &mut Features,
+	cached: Option<&Instance>,
+) {
+	match instance::limits(&config.instance) {
+		Ok(limits) => {
+			features.max_length = Some(limits.max_characters);
+			features.url_length = limits.characters_reserved_per_url;
+		},
+		Err(e) => {
+			log::warn!(
+				"Failed to find out how long posts can be: {e}"
+			);
+			if let Some(cached) = cached {
+				features.max_length = cached.features.max_length;
+				features.url_length = cached.features.url_length;
+			}
+		},
+	}
+}
+
+/// Asks the instance what software it runs, remembering the answer in
+/// the cache, and returns what it can do. If it can't be asked, or
+/// this is a dry run, what was found out last time is used instead.
+pub fn detect(config: &Config) -> Features {
+	// Bluesky servers don't have NodeInfo, but do all have the same
+	// limit of 300 graphemes
+	if config.backend == Backend::Bluesky {
+		return Features {
+			max_length: Some(300),
+			graphemes: true,
+			..Features::default()
+		};
+	}
+	let cached = Instance::load(config).unwrap_or_else(|e| {
+		log::error!("{e}");
+		None
+	});
+	if config.dry_run {
+		return cached.map_or_else(Features::default, |i| i.features);
+	}
+	let instance = match fetch(&config.instance) {
+		Ok((software, metadata)) => {
+			let mut features = features(&software, &metadata);
+			if config.backend == Backend::Mastodon {
+				limit(config, &mut features, cached.as_ref());
+			}
+			let instance = Instance::new(config, software, features);
+			if let Err(e) = instance.dump() {
+				log::error!("{e}");
+			}
+			instance
+		},
+		Err(e) => {
+			log::warn!(
+				"Failed to find out what the instance runs: {e}"
+			);
+			match cached {
+				Some(cached) => cached,
+				None => return Features::default(),
+			}
+		},
+	};
+	let software = &instance.software;
+	log::info!(
+		"{} runs {} {}",
+		config.instance,
+		software.name,
+		software.version
+	);
+	check(config, software, &instance.features);
+	instance.features
+}
@@ -0,0 +1,162 @@
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Utc};
+use rand::{distributions::Distribution, Rng, SeedableRng};
+use serde::Deserialize;
+
+use super::post::{self, PostCfg};
+use crate::data::{
+	config::{Backend, Config},
+	history::History,
+	secrets::Secrets,
+};
+
+// How long to wait before trying to announce a result again, if it
+// failed the first time.
+const RETRY: Duration = Duration::minutes(5);
+
+#[derive(Deserialize)]
+pub struct Choice {
+	pub title: String,
+	pub votes_count: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct Poll {
+	pub expired: bool,
+	pub options: Vec<Choice>,
+}
+
+// GET /api/v1/polls/:id
+fn fetch(
+	config: &Config,
+	secrets: &Secrets,
+	id: &str,
+) -> Result<Poll> {
+	let instance = &config.instance;
+	if config.backend == Backend::Misskey {
+		return super::misskey::poll(instance, secrets, id);
+	}
+	let text = super::request::send(|client| {
+		client
+			.get(super::url(instance, &format!("/api/v1/polls/{id}")))
+			.header("Authorization", &secrets.token)
+	})?
+	.error_for_status()?
+	.text()?;
+	Ok(serde_json::from_str(&text)?)
+}
+
+fn votes(n: u64) -> String {
+	match n {
+		1 => String::from("1 vote"),
+		n => format!("{n} votes"),
+	}
+}
+
+/// Describes who won a poll.
+fn summary(poll: &Poll) -> String {
+	let count = |choice: &Choice| choice.votes_count.unwrap_or(0);
+	let total = poll.options.iter().map(count).sum::<u64>();
+	let most = poll.options.iter().map(count).max().unwrap_or(0);
+	if total == 0 {
+		return String::from("Nobody voted in this poll.");
+	}
+	let winners = poll
+		.options
+		.iter()
+		.filter(|choice| count(choice) == most)
+		.map(|choice| choice.title.as_str())
+		.collect::<Vec<_>>();
+	match winners.as_slice() {
+		[winner] => format!(
+			"The results are in: {winner} won, with {}% of {}.",
+			most * 100 / total,
+			votes(total)
+		),
+		[rest @ .., last] => format!(
+			"The results are in: it's a tie between {} and {last}, \
+			with {} each.",
+			rest.join(", "),
+			votes(most)
+		),
+		[] => unreachable!(),
+	}
+}
+
+/// When results should next be checked for, if there are any polls
+/// still waiting for one.
+pub fn next(history: &History) -> Option<Instant> {
+	let now = Utc::now();
+	history
+		.open_polls()
+		.filter_map(|entry| entry.poll.as_ref())
+		// anything already closed has just failed to be announced,
+		// and anything else is announced as soon as it closes
+		.map(|poll| match poll.expires_at <= now {
+			true => now + RETRY,
+			false => poll.expires_at,
+		})
+		.min()
+		.map(|at| {
+			Instant::now() + (at - now).to_std().unwrap_or_default()
+		})
+}
+
+/// Replies to every post whose poll has closed with the result.
+pub fn announce<T, D>(cfg: &mut PostCfg<T, D>) -> Result<()>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	let secrets = cfg.secrets.ok_or(anyhow!("Not authorised."))?;
+	let now = Utc::now();
+	let closed = cfg
+		.history
+		.posts
+		.iter()
+		.enumerate()
+		.filter(|(_, entry)| {
+			!entry.deleted
+				&& !entry.scheduled
+				&& entry.poll.as_ref().is_some_and(|p| {
+					p.result.is_none() && p.expires_at <= now
+				})
+		})
+		.map(|(i, _)| i)
+		.collect::<Vec<_>>();
+	for i in closed {
+		let entry = &cfg.history.posts[i];
+		let id = &entry.poll.as_ref().unwrap().id;
+		let poll = match fetch(cfg.config, secrets, id) {
+			Ok(poll) if poll.expired => poll,
+			Ok(_) => continue,
+			Err(e) => {
+				log::error!(
+					"Failed to get the results of poll {id}: {e}"
+				);
+				continue;
+			},
+		};
+		log::info!("Announcing the results of poll {id}");
+		let visibility =
+			entry.visibility.unwrap_or(cfg.config.visibility.default);
+		match post::follow_up(
+			cfg,
+			&summary(&poll),
+			&entry.id,
+			visibility,
+		) {
+			Ok(result) => {
+				cfg.history.posts[i].poll.as_mut().unwrap().result =
+					Some(result);
+				cfg.history.dump()?;
+			},
+			Err(e) => {
+				log::error!("Failed to announce poll results: {e}")
+			},
+		}
+	}
+	Ok(())
+}
@@ -1,59 +1,985 @@
-use anyhow::Result;
-use rand::{distributions::Distribution, Rng};
-use serde::Serialize;
+use std::fs;
 
-use crate::data::{config::Config, secrets::Secrets};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Datelike, Duration, Local, Utc};
+use rand::{
+	distributions::{Distribution, WeightedIndex},
+	Rng, SeedableRng,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use super::{bluesky, media, misskey, words::Words};
+use crate::data::{
+	card::Card,
+	config::{
+		Backend, Config, Seed, Source, Visibility, VisibilityMode,
+	},
+	english::Dictionary,
+	filter::Filter,
+	history::{Entry, History, Poll},
+	instance::Features,
+	pending::Pending,
+	secrets::Secrets,
+	template::{Template, Values},
+};
+
+/// How long Mastodon remembers an `Idempotency-Key` for.
+const KEY_MINUTES: i64 = 60;
 
 pub struct PostCfg<'cfg, R, D>
 where
-	R: Rng,
+	R: Rng + SeedableRng,
 	D: Distribution<usize>,
 {
 	pub config: &'cfg Config,
-	pub secrets: &'cfg Secrets,
+	pub secrets: Option<&'cfg Secrets>,
+	pub words: Words,
+	pub dictionary: Dictionary,
+	pub filter: Filter,
+	pub history: History,
 	pub rng: R,
 	pub dist: D,
+	/// Inventor weights adjusted by how well their posts have done,
+	/// which are used instead of `dist` when set.
+	pub adaptive: Option<WeightedIndex<f64>>,
+	pub count: u64,
+	pub day: Option<u64>,
+	/// What the instance can do.
+	pub features: Features,
+	/// Accounts each new post is cross-posted to.
+	pub mirrors: Vec<Mirror<'cfg>>,
 }
 
-pub fn post<T, D>(cfg: &mut PostCfg<T, D>) -> Result<()>
+/// An account that only posts copies of another account's posts.
+pub struct Mirror<'cfg> {
+	pub config: &'cfg Config,
+	pub secrets: Option<&'cfg Secrets>,
+	pub history: History,
+	pub features: Features,
+}
+
+struct Draft<'cfg> {
+	inventor: String,
+	/// The inventor in the config the name was expanded from.
+	rule: Option<&'cfg str>,
+	invention: String,
+	template: &'cfg Template,
+	source: Source,
+	date: String,
+	status: String,
+	spoiler: Option<&'cfg str>,
+	visibility: Visibility,
+	poll: bool,
+	/// When the post will be made.
+	at: DateTime<Utc>,
+	scheduled: bool,
+}
+
+/// A status being replied to.
+pub struct Reply<'a> {
+	pub id: &'a str,
+	pub acct: &'a str,
+	pub visibility: Visibility,
+}
+
+fn draft<'cfg, T, D>(
+	cfg: &mut PostCfg<'cfg, T, D>,
+	inventor: Option<&str>,
+	at: DateTime<Utc>,
+) -> Result<Draft<'cfg>>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	let config = cfg.config;
+	let (inventor, rule) = match inventor {
+		Some(inventor) => (inventor.to_owned(), None),
+		None => {
+			let index = match &cfg.adaptive {
+				Some(adaptive) => cfg.rng.sample(adaptive),
+				None => cfg.rng.sample(&cfg.dist),
+			};
+			let inventor = &config.inventors[index];
+			match (&inventor.rule, &config.grammar) {
+				(Some(rule), Some(grammar)) => (
+					rule.expand(grammar, &mut cfg.rng),
+					Some(inventor.name.as_str()),
+				),
+				_ => (inventor.name.clone(), None),
+			}
+		},
+	};
+	let (invention, source) = super::words::gen_item(
+		&cfg.words,
+		&cfg.dictionary,
+		&mut cfg.rng,
+	)?;
+	let templates = &config.templates.status;
+	let template = &templates[cfg.rng.sample(WeightedIndex::new(
+		templates.iter().map(|t| t.weight),
+	)?)];
+	let date =
+		at.with_timezone(&Local).format("%Y-%m-%d").to_string();
+	let status = template.render(&Values {
+		inventor: &inventor,
+		invention: &invention,
+		date: &date,
+		count: cfg.count + 1,
+	});
+	let spoiler = config
+		.spoiler
+		.as_ref()
+		.filter(|s| cfg.rng.gen_bool(s.chance))
+		.map(|s| s.text.as_str());
+	let visibility = visibility(
+		&cfg.config.visibility,
+		&cfg.history,
+		at,
+		&mut cfg.rng,
+	)?;
+	let poll = config.poll.enable
+		&& cfg.features.polls
+		&& cfg.rng.gen_bool(config.poll.chance);
+	Ok(Draft {
+		inventor,
+		rule,
+		invention,
+		template,
+		source,
+		date,
+		status,
+		spoiler,
+		visibility,
+		poll,
+		at,
+		scheduled: false,
+	})
+}
+
+/// Picks the visibility of a post made at `at`, which is whichever of
+/// `every` is due first, or else one of the weighted or default ones.
+fn visibility(
+	mode: &VisibilityMode,
+	history: &History,
+	at: DateTime<Utc>,
+	rng: &mut impl Rng,
+) -> Result<Visibility> {
+	let due = mode.every.iter().find(|every| {
+		history.last(every.visibility).is_none_or(|last| {
+			at - last >= Duration::hours(every.hours as i64)
+		})
+	});
+	if let Some(every) = due {
+		return Ok(every.visibility);
+	}
+	if mode.weights.is_empty() {
+		return Ok(mode.default);
+	}
+	let dist = WeightedIndex::new(mode.weights.values())?;
+	let (visibility, _) =
+		mode.weights.iter().nth(rng.sample(dist)).unwrap();
+	Ok(*visibility)
+}
+
+/// Renders the card for a post, returning the PNG and its alt text.
+fn card<T, D>(
+	cfg: &PostCfg<T, D>,
+	draft: &Draft,
+) -> Result<(Vec<u8>, String)>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	let card = Card {
+		values: Values {
+			inventor: &draft.inventor,
+			invention: &draft.invention,
+			date: &draft.date,
+			count: cfg.count + 1,
+		},
+	};
+	let alt = card.alt(cfg.config.card.alt.as_ref());
+	let image = card.render()?;
+	if let Some(dir) = &cfg.config.card.output {
+		fs::create_dir_all(dir)?;
+		let path = dir.join(format!("{}.png", cfg.count + 1));
+		fs::write(&path, &image)
+			.context(format!("Failed to save card to {:?}.", path))?;
+	}
+	Ok((image, alt))
+}
+
+/// Whether a draft is short enough for the instance, and for every
+/// account it'll be cross-posted to. Content warnings count towards
+/// the length too.
+fn fits<T, D>(
+	cfg: &PostCfg<T, D>,
+	draft: &Draft,
+	prefix: &str,
+) -> bool
 where
-	T: Rng,
+	T: Rng + SeedableRng,
 	D: Distribution<usize>,
 {
-	#[derive(Serialize)]
-	struct Request<'a> {
-		content_type: &'static str,
-		visibility: &'a str,
-		status: &'a str,
+	let spoiler = draft.spoiler.unwrap_or_default();
+	let text = format!("{spoiler}{prefix}{}", draft.status);
+	// replies, which have a prefix, aren't cross-posted
+	let mut mirrors = cfg.mirrors.iter().filter(|mirror| {
+		prefix.is_empty()
+			&& copies(mirror, draft.visibility, draft.spoiler)
+	});
+	cfg.features.fits(&text)
+		&& mirrors.all(|mirror| mirror.features.fits(&text))
+}
+
+/// Drafts posts until one passes the filter, isn't a repeat, and is
+/// short enough with `prefix` in front of it.
+fn pick<'cfg, T, D>(
+	cfg: &mut PostCfg<'cfg, T, D>,
+	inventor: Option<&str>,
+	prefix: &str,
+	at: DateTime<Utc>,
+) -> Result<Draft<'cfg>>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	let attempts = cfg.config.attempts;
+	let mut attempt = 0;
+	loop {
+		let draft = draft(cfg, inventor, at)?;
+		// the whole post is checked too, since an entry can span the
+		// template and what was put in it
+		let blocked = cfg
+			.filter
+			.check(&draft.invention)
+			.or_else(|| cfg.filter.check(&draft.status))
+			.or_else(|| {
+				draft.spoiler.and_then(|s| cfg.filter.check(s))
+			});
+		let too_long = !fits(cfg, &draft, prefix);
+		if let Some(pattern) = blocked {
+			log::warn!(
+				"Rejected {:?}, which matched the blocklist entry {pattern}.",
+				draft.status
+			);
+		} else if too_long {
+			log::info!(
+				"{:?} is too long to post, trying again.",
+				draft.status
+			);
+		} else if cfg.history.is_repeat(
+			cfg.config,
+			&draft.inventor,
+			&draft.invention,
+		) {
+			log::info!(
+				"{} inventing {} has been posted recently, trying again.",
+				draft.inventor,
+				draft.invention
+			);
+		} else {
+			return Ok(draft);
+		}
+		attempt += 1;
+		if attempt >= attempts {
+			return Err(anyhow!(
+				"Could not come up with an acceptable post after \
+				{attempts} attempts."
+			));
+		}
 	}
+}
+
+pub fn post<T, D>(cfg: &mut PostCfg<T, D>) -> Result<()>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
 	log::info!("Sending post");
-	let inventor = &cfg.config.inventors[cfg.rng.sample(&cfg.dist)];
-	let invention = super::words::gen_item()?;
-	let status =
-		&format!("I can't believe {inventor} invented {invention}");
+	settle(cfg)?;
+	let now = Utc::now();
+	reseed(cfg, now);
+	let draft = pick(cfg, None, "", now)?;
+	publish(cfg, draft, None)
+}
+
+/// Schedules a post to be made by the instance at a later time.
+pub fn schedule<T, D>(
+	cfg: &mut PostCfg<T, D>,
+	at: DateTime<Utc>,
+) -> Result<()>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	log::info!("Scheduling post for {at}");
+	settle(cfg)?;
+	reseed(cfg, at);
+	let mut draft = pick(cfg, None, "", at)?;
+	draft.scheduled = true;
+	publish(cfg, draft, None)
+}
+
+/// Sends the pending post, if there is one, before a new one is
+/// drafted. Only one post can be pending at a time, so a new one
+/// would otherwise take its place and it would never be sent.
+fn settle<T, D>(cfg: &mut PostCfg<T, D>) -> Result<()>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	resume(cfg.config, cfg.secrets, &mut cfg.history).context(
+		"A post that may not have been made couldn't be sent \
+		again, so no new post was made.",
+	)
+}
+
+/// With a daily seed, the generator is reseeded whenever the day
+/// changes.
+fn reseed<T, D>(cfg: &mut PostCfg<T, D>, at: DateTime<Utc>)
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	if cfg.config.seed == Some(Seed::Daily) {
+		let day = at.date_naive().num_days_from_ce() as u64;
+		if cfg.day != Some(day) {
+			cfg.rng = T::seed_from_u64(day);
+			cfg.day = Some(day);
+		}
+	}
+}
+
+pub fn reply<T, D>(
+	cfg: &mut PostCfg<T, D>,
+	inventor: &str,
+	to: &Reply,
+) -> Result<()>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	log::info!("Replying to {}", to.acct);
+	settle(cfg)?;
+	let prefix = format!("@{} ", to.acct);
+	let mut draft = pick(cfg, Some(inventor), &prefix, Utc::now())?;
+	draft.poll = false;
+	draft.status = format!("{prefix}{}", draft.status);
+	// never reply more publicly than the mention was
+	draft.visibility =
+		cfg.config.mentions.visibility.max(to.visibility);
+	publish(cfg, draft, Some(to.id))
+}
+
+#[derive(Serialize)]
+struct PollRequest<'a> {
+	options: &'a [String],
+	expires_in: u64,
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	content_type: Option<&'static str>,
+	visibility: Visibility,
+	status: &'a str,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	spoiler_text: Option<&'a str>,
+	sensitive: bool,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	language: Option<&'a str>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	media_ids: Vec<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	in_reply_to_id: Option<&'a str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	poll: Option<PollRequest<'a>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	scheduled_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+pub struct Response {
+	pub id: String,
+	pub url: Option<String>,
+	pub poll: Option<PollResponse>,
+}
+
+#[derive(Deserialize)]
+pub struct PollResponse {
+	pub id: String,
+	pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Turns a request into the body to send for the config's backend.
+fn body(
+	config: &Config,
+	secrets: &Secrets,
+	params: &Request,
+) -> Result<String> {
+	Ok(match config.backend {
+		Backend::Mastodon => serde_json::to_string(params)?,
+		Backend::Misskey => serde_json::to_string(&misskey::Note {
+			text: params.status,
+			cw: params.spoiler_text,
+			visibility: misskey::visibility(params.visibility),
+			file_ids: &params.media_ids,
+			reply_id: params.in_reply_to_id,
+			poll: params.poll.as_ref().map(|poll| {
+				misskey::NotePoll {
+					choices: poll.options,
+					multiple: false,
+					expired_after: poll.expires_in * 1000,
+				}
+			}),
+		})?,
+		Backend::Bluesky => bluesky::body(
+			config,
+			secrets,
+			params.status,
+			&params.media_ids,
+		)?,
+	})
+}
+
+/// Uploads a card for the config's backend, returning the id to
+/// attach it with.
+fn upload(
+	config: &Config,
+	secrets: &Secrets,
+	image: Vec<u8>,
+	alt: &str,
+) -> Result<String> {
+	match config.backend {
+		Backend::Mastodon => {
+			media::upload(&config.instance, secrets, image, alt)
+		},
+		Backend::Misskey => {
+			misskey::upload(config, secrets, image, alt)
+		},
+		Backend::Bluesky => {
+			bluesky::upload(config, secrets, image, alt)
+		},
+	}
+}
+
+// POST /api/v1/statuses
+// This is sent as JSON, since polls need a list of options, which
+// can't be put in a form.
+fn send(
+	config: &Config,
+	secrets: &Secrets,
+	body: &str,
+	key: &str,
+) -> Result<Response> {
+	let instance = &config.instance;
+	match config.backend {
+		Backend::Mastodon => {},
+		// Misskey has no equivalent to idempotency keys
+		Backend::Misskey => {
+			return misskey::create(instance, secrets, body)
+		},
+		// and Bluesky posts have their key in the body
+		Backend::Bluesky => {
+			return bluesky::create(config, secrets, body)
+		},
+	}
+	let text = super::request::send(|client| {
+		client
+			.post(super::url(instance, "/api/v1/statuses"))
+			.header("Content-Type", "application/json")
+			.body(body.to_owned())
+			.header("Authorization", &secrets.token)
+			.header("Idempotency-Key", key)
+	})?
+	.error_for_status()?
+	.text()?;
+	Ok(serde_json::from_str::<Response>(&text)?)
+}
+
+fn publish<T, D>(
+	cfg: &mut PostCfg<T, D>,
+	draft: Draft,
+	reply: Option<&str>,
+) -> Result<()>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	let status = &draft.status;
+	let config = cfg.config;
+	// Mastodon won't attach both media and a poll to the same post.
+	let card = match (config.card.enable, draft.poll) {
+		(true, false) => Some(card(cfg, &draft)?),
+		_ => None,
+	};
+	let poll = draft.poll.then(|| PollRequest {
+		options: &config.poll.options,
+		expires_in: config.poll.hours * 60 * 60,
+	});
 
-	if cfg.config.dry_run {
+	if config.dry_run {
+		if let Some(spoiler) = draft.spoiler {
+			println!("CW: {spoiler}");
+		}
+		if draft.scheduled {
+			println!(
+				"Scheduled for {}:",
+				draft
+					.at
+					.with_timezone(&Local)
+					.format("%Y-%m-%d %H:%M")
+			);
+		}
 		println!("{status}");
+		if let Some((_, alt)) = &card {
+			println!("Image: {alt}");
+		}
+		if poll.is_some() {
+			println!(
+				"Poll: {} ({} hours)",
+				config.poll.options.join(" / "),
+				config.poll.hours
+			);
+		}
+		if reply.is_none() && !draft.scheduled {
+			for mirror in &cfg.mirrors {
+				if !copies(mirror, draft.visibility, draft.spoiler) {
+					continue;
+				}
+				let name = mirror.config.name.as_deref();
+				println!(
+					"Cross-posting to {}",
+					name.unwrap_or_default()
+				);
+			}
+		}
+		cfg.count += 1;
 		return Ok(());
 	}
 
+	let secrets = cfg.secrets.ok_or(anyhow!("Not authorised."))?;
+	let media_ids = match &card {
+		Some((image, alt)) => {
+			vec![upload(config, secrets, image.clone(), alt)?]
+		},
+		None => Vec::new(),
+	};
 	let params = Request {
-		content_type: "text/plain",
-		visibility: (&cfg.config.visibility).into(),
+		content_type: cfg.features.content_type(),
+		visibility: draft.visibility,
 		status,
+		spoiler_text: draft.spoiler,
+		sensitive: config.sensitive,
+		language: config.language.as_deref(),
+		media_ids,
+		in_reply_to_id: reply,
+		poll,
+		scheduled_at: draft.scheduled.then_some(draft.at),
+	};
+	let body = body(config, secrets, &params)?;
+	let entry = Entry {
+		timestamp: match draft.scheduled {
+			true => draft.at,
+			false => Utc::now(),
+		},
+		inventor: draft.inventor,
+		invention: draft.invention,
+		template: draft.template.text.clone(),
+		status: draft.status,
+		id: String::new(),
+		url: None,
+		visibility: Some(draft.visibility),
+		in_reply_to: reply.map(str::to_owned),
+		poll: None,
+		scheduled: draft.scheduled,
+		deleted: false,
+		rule: draft.rule.map(str::to_owned),
+		source: Some(draft.source),
+		engagement: None,
 	};
+	let pending = Pending::new(config, body, entry);
+	save(config, &pending)?;
+	let entry = finish(config, secrets, &mut cfg.history, pending)?;
+	cfg.count += 1;
 
-	let url =
-		format!("https://{}/api/v1/statuses", cfg.config.instance,);
-	super::RQ_CLIENT
-		.get()
-		.unwrap()
-		.post(url)
-		.form(&params)
-		.header("Authorization", &cfg.secrets.token)
-		.header("Idempotency-Key", status)
-		.send()?
-		.error_for_status()?;
+	// replies only make sense where the mention was, and scheduled
+	// posts haven't been made yet
+	if reply.is_none() && !draft.scheduled {
+		for mirror in &mut cfg.mirrors {
+			let spoiler = draft.spoiler;
+			let result = crosspost(mirror, &entry, spoiler, &card);
+			if let Err(e) = result {
+				let name = mirror.config.name.as_deref();
+				log::error!(
+					"{:?}",
+					e.context(format!(
+						"Failed to cross-post to {}.",
+						name.unwrap_or_default()
+					))
+				);
+			}
+		}
+	}
+	Ok(())
+}
+
+/// Whether a post is copied to a mirror. Bluesky posts are all
+/// public and have no content warnings, so only posts that are
+/// already like that are copied there.
+fn copies(
+	mirror: &Mirror,
+	visibility: Visibility,
+	spoiler: Option<&str>,
+) -> bool {
+	mirror.config.backend != Backend::Bluesky
+		|| (visibility <= Visibility::Unlisted && spoiler.is_none())
+}
+
+/// Posts a copy of a post that's just been made to another account.
+fn crosspost(
+	mirror: &mut Mirror,
+	entry: &Entry,
+	spoiler: Option<&str>,
+	card: &Option<(Vec<u8>, String)>,
+) -> Result<()> {
+	let config = mirror.config;
+	let visibility = entry.visibility.unwrap_or_default();
+	if !copies(mirror, visibility, spoiler) {
+		log::info!(
+			"Not cross-posting to {}, since the post isn't public or \
+			has a content warning.",
+			config.name.as_deref().unwrap_or_default()
+		);
+		return Ok(());
+	}
+	let secrets = mirror.secrets.ok_or(anyhow!("Not authorised."))?;
+	log::info!(
+		"Cross-posting to {}",
+		config.name.as_deref().unwrap_or_default()
+	);
+	let media_ids = match card {
+		Some((image, alt)) => {
+			vec![upload(config, secrets, image.clone(), alt)?]
+		},
+		None => Vec::new(),
+	};
+	let params = Request {
+		content_type: mirror.features.content_type(),
+		visibility: entry
+			.visibility
+			.unwrap_or(config.visibility.default),
+		status: &entry.status,
+		spoiler_text: spoiler,
+		sensitive: config.sensitive,
+		language: config.language.as_deref(),
+		media_ids,
+		in_reply_to_id: None,
+		poll: None,
+		scheduled_at: None,
+	};
+	let body = body(config, secrets, &params)?;
+	let entry = Entry {
+		timestamp: Utc::now(),
+		id: String::new(),
+		url: None,
+		poll: None,
+		engagement: None,
+		..entry.clone()
+	};
+	// the mirror's own pending post has to go first, as with `settle`
+	resume(config, Some(secrets), &mut mirror.history)?;
+	let pending = Pending::new(config, body, entry);
+	save(config, &pending)?;
+	finish(config, secrets, &mut mirror.history, pending)?;
+	Ok(())
+}
+
+/// Saves a post before it's sent, so that the same key is used if the
+/// bot has to send it again. Misskey has no keys, so its posts are
+/// never sent again, and there's no point saving them.
+fn save(config: &Config, pending: &Pending) -> Result<()> {
+	match config.backend {
+		Backend::Misskey => Ok(()),
+		_ => pending.dump(),
+	}
+}
+
+/// Sends a saved post and records it in the history, returning what
+/// was recorded.
+fn finish(
+	config: &Config,
+	secrets: &Secrets,
+	history: &mut History,
+	mut pending: Pending,
+) -> Result<Entry> {
+	let (body, key) = (&pending.body, &pending.key);
+	let response = match send(config, secrets, body, key) {
+		Ok(response) => response,
+		Err(e) => {
+			// the instance turned the post down, so there's no point
+			// sending it again
+			if refused(&e) {
+				pending.clear()?;
+			}
+			return Err(e);
+		},
+	};
+
+	let entry = &mut pending.entry;
+	entry.id = response.id;
+	entry.url = response.url;
+	// scheduled posts don't have a poll yet, and their id is only
+	// good for cancelling them
+	let poll = response.poll.filter(|_| !entry.scheduled);
+	entry.poll = poll.map(|poll| Poll {
+		expires_at: poll.expires_at.unwrap_or_else(|| {
+			Utc::now() + Duration::hours(config.poll.hours as i64)
+		}),
+		id: poll.id,
+		result: None,
+	});
+	history.push(entry.clone())?;
+	pending.clear()?;
+	Ok(pending.entry)
+}
+
+fn refused(e: &anyhow::Error) -> bool {
+	e.downcast_ref::<reqwest::Error>()
+		.and_then(reqwest::Error::status)
+		.is_some_and(|status| {
+			status.is_client_error()
+				&& status != StatusCode::TOO_MANY_REQUESTS
+		})
+}
 
+/// Sends the post saved by an earlier attempt, which stopped before
+/// it knew whether the post was made. The instance only remembers
+/// keys for a while, after which the post can't safely be sent again.
+pub fn resume(
+	config: &Config,
+	secrets: Option<&Secrets>,
+	history: &mut History,
+) -> Result<()> {
+	let Some(secrets) = secrets else {
+		return Ok(());
+	};
+	let Some(pending) = Pending::load(config)? else {
+		return Ok(());
+	};
+	if config.backend == Backend::Misskey {
+		log::warn!(
+			"Not sending a post again, since Misskey can't tell if \
+			it was already made: {}",
+			pending.entry.status
+		);
+		return pending.clear();
+	}
+	if Utc::now() - pending.created > Duration::minutes(KEY_MINUTES) {
+		let created = pending.created.with_timezone(&Local);
+		log::warn!(
+			"Giving up on a post from {}, it may or may not have been \
+			made: {}",
+			created.format("%Y-%m-%d %H:%M"),
+			pending.entry.status
+		);
+		return pending.clear();
+	}
+	log::info!("Sending a post left over from an earlier attempt.");
+	finish(config, secrets, history, pending)?;
 	Ok(())
 }
+
+/// Replies to one of the bot's own posts with some plain text, returning
+/// the id of the reply.
+pub fn follow_up<T, D>(
+	cfg: &PostCfg<T, D>,
+	text: &str,
+	in_reply_to: &str,
+	visibility: Visibility,
+) -> Result<String>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	let secrets = cfg.secrets.ok_or(anyhow!("Not authorised."))?;
+	let params = Request {
+		content_type: cfg.features.content_type(),
+		visibility,
+		status: text,
+		spoiler_text: None,
+		sensitive: false,
+		language: cfg.config.language.as_deref(),
+		media_ids: Vec::new(),
+		in_reply_to_id: Some(in_reply_to),
+		poll: None,
+		scheduled_at: None,
+	};
+	// there's only ever one follow up to a post, so its id makes a key
+	// which stays the same between runs
+	let key = format!("follow-up-{in_reply_to}");
+	let body = body(cfg.config, secrets, &params)?;
+	Ok(send(cfg.config, secrets, &body, &key)?.id)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeMap;
+
+	use rand::rngs::StdRng;
+
+	use super::*;
+	use crate::api::mock;
+
+	fn configured(visibility: &str) -> VisibilityMode {
+		toml::from_str::<Config>(&format!(
+			"instance = \"example.com\"\ncache.path = \"/cache\"\n\
+			visibility = {visibility}\n"
+		))
+		.unwrap()
+		.visibility
+	}
+
+	fn posted(visibility: &str, hours: i64) -> Entry {
+		toml::from_str(&format!(
+			"timestamp = {:?}\nvisibility = {visibility:?}\n\
+			inventor = \"\"\ninvention = \"\"\ntemplate = \"\"\n\
+			status = \"\"\nid = \"\"\n",
+			(Utc::now() - Duration::hours(hours)).to_rfc3339()
+		))
+		.unwrap()
+	}
+
+	#[test]
+	fn visibility_every() {
+		let mode = configured(
+			"{ default = \"public\", every = [\
+			{ visibility = \"private\", hours = 24 }, \
+			{ visibility = \"direct\", hours = 48 }] }",
+		);
+		let mut rng = StdRng::seed_from_u64(0);
+		let mut pick = |posts: &[(&str, i64)]| {
+			let mut history = History::default();
+			for (visibility, hours) in posts {
+				history.posts.push(posted(visibility, *hours));
+			}
+			visibility(&mode, &history, Utc::now(), &mut rng).unwrap()
+		};
+		// anything that's never been used is due straight away
+		assert_eq!(pick(&[]), Visibility::Private);
+		assert_eq!(pick(&[("private", 2)]), Visibility::Direct);
+		assert_eq!(
+			pick(&[("private", 2), ("direct", 1)]),
+			Visibility::Public
+		);
+		assert_eq!(
+			pick(&[("private", 25), ("direct", 1)]),
+			Visibility::Private
+		);
+		assert_eq!(
+			pick(&[("private", 2), ("direct", 49)]),
+			Visibility::Direct
+		);
+		// only the latest post of each visibility counts
+		assert_eq!(
+			pick(&[("private", 30), ("direct", 1), ("private", 3)]),
+			Visibility::Public
+		);
+	}
+
+	#[test]
+	fn visibility_weights() {
+		let mode =
+			configured("{ weights = { public = 3, private = 1 } }");
+		let mut rng = StdRng::seed_from_u64(0);
+		let history = History::default();
+		let mut counts = BTreeMap::new();
+		for _ in 0..1000 {
+			let visibility =
+				visibility(&mode, &history, Utc::now(), &mut rng)
+					.unwrap();
+			*counts.entry(visibility).or_insert(0) += 1;
+		}
+		assert_eq!(counts.len(), 2);
+		let public = counts[&Visibility::Public];
+		assert!((650..850).contains(&public), "{public}");
+
+		let mode = configured("\"direct\"");
+		assert_eq!(
+			visibility(&mode, &history, Utc::now(), &mut rng)
+				.unwrap(),
+			Visibility::Direct
+		);
+	}
+
+	fn entry() -> Entry {
+		toml::from_str(&format!(
+			"timestamp = {:?}\n\
+			inventor = \"Ada\"\n\
+			invention = \"the loom\"\n\
+			template = \"\"\n\
+			status = \"Ada invented the loom\"\n\
+			id = \"\"\n",
+			Utc::now().to_rfc3339()
+		))
+		.unwrap()
+	}
+
+	#[test]
+	fn resumes_pending_posts() {
+		let (url, server) = mock::serve(
+			"application/json",
+			vec![(
+				200,
+				r#"{"id":"7","url":"https://x/7","poll":null}"#,
+			)],
+		);
+		let dir = std::env::temp_dir().join(format!(
+			"inventor_bot_resume_{}",
+			std::process::id()
+		));
+		let config = toml::from_str::<Config>(&format!(
+			"instance = \"{url}\"\ncache.path = {:?}\n",
+			dir
+		))
+		.unwrap();
+		let secrets =
+			toml::from_str::<Secrets>("token = \"Bearer token\"")
+				.unwrap();
+		let mut history = History::load(&config).unwrap();
+
+		// too old for the instance to still know its key
+		let mut pending =
+			Pending::new(&config, String::from("{}"), entry());
+		pending.created = Utc::now() - Duration::hours(2);
+		pending.dump().unwrap();
+		resume(&config, Some(&secrets), &mut history).unwrap();
+		assert!(Pending::load(&config).unwrap().is_none());
+		assert!(history.posts.is_empty());
+
+		let pending =
+			Pending::new(&config, String::from("{\"a\":1}"), entry());
+		pending.dump().unwrap();
+		resume(&config, Some(&secrets), &mut history).unwrap();
+		assert!(Pending::load(&config).unwrap().is_none());
+		assert_eq!(history.posts.len(), 1);
+		assert_eq!(history.posts[0].id, "7");
+		assert_eq!(
+			history.posts[0].url.as_deref(),
+			Some("https://x/7")
+		);
+
+		// only the recent post was sent, with the key it was saved with
+		let seen = server.join().unwrap();
+		assert_eq!(seen.len(), 1);
+		assert_eq!(seen[0].line, "POST /api/v1/statuses HTTP/1.1");
+		assert_eq!(
+			seen[0].header("Idempotency-Key"),
+			Some(pending.key.as_str())
+		);
+		assert_eq!(seen[0].body, "{\"a\":1}");
+		fs::remove_dir_all(dir).unwrap();
+	}
+}
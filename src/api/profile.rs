@@ -0,0 +1,285 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{Local, Utc};
+use rand::{distributions::Distribution, Rng, SeedableRng};
+use reqwest::blocking::multipart::{Form, Part};
+use serde_json::json;
+
+use super::post::PostCfg;
+use crate::data::{
+	config::{Config, Field, Profile},
+	history::History,
+	profile::{self, Applied, FIELDS},
+	secrets::Secrets,
+	template::Values,
+};
+
+/// An image to set as the avatar or header.
+struct Image {
+	name: &'static str,
+	file_name: String,
+	mime: &'static str,
+	data: Vec<u8>,
+	hash: String,
+}
+
+impl Image {
+	fn load(name: &'static str, path: &Path) -> Result<Self> {
+		let data = fs::read(path).context(format!(
+			"Failed to read the profile's {name} at {:?}.",
+			path
+		))?;
+		Ok(Self {
+			name,
+			file_name: path
+				.file_name()
+				.map(|name| name.to_string_lossy().into_owned())
+				.unwrap_or_default(),
+			mime: profile::mime(path)
+				.ok_or(anyhow!("Unknown image type {:?}.", path))?,
+			hash: profile::hash(&data),
+			data,
+		})
+	}
+}
+
+/// The text parts of the profile, as the form fields they're sent as.
+/// The bio is filled in from the posts that are up on the account,
+/// leaving out replies.
+fn text(
+	profile: &Profile,
+	history: &History,
+) -> Vec<(String, String)> {
+	let mut out = Vec::new();
+	if let Some(name) = &profile.display_name {
+		out.push((String::from("display_name"), name.clone()));
+	}
+	if let Some(bio) = &profile.bio {
+		let mut posts = history.posts.iter().filter(|entry| {
+			!entry.id.is_empty()
+				&& entry.in_reply_to.is_none()
+				&& !entry.scheduled
+				&& !entry.deleted
+		});
+		let latest = posts.next_back();
+		let date = Local::now().format("%Y-%m-%d").to_string();
+		let note = bio.render(&Values {
+			inventor: latest.map_or("", |entry| &entry.inventor),
+			invention: latest.map_or("", |entry| &entry.invention),
+			date: &date,
+			count: latest.map_or(0, |_| posts.count() as u64 + 1),
+		});
+		out.push((String::from("note"), note));
+	}
+	if let Some(fields) = &profile.fields {
+		let blank = Field {
+			name: String::new(),
+			value: String::new(),
+		};
+		let padding = FIELDS.saturating_sub(fields.len());
+		for (i, field) in fields
+			.iter()
+			.chain(std::iter::repeat_n(&blank, padding))
+			.enumerate()
+		{
+			let key = format!("fields_attributes[{i}]");
+			out.push((format!("{key}[name]"), field.name.clone()));
+			out.push((format!("{key}[value]"), field.value.clone()));
+		}
+	}
+	for (key, flag) in
+		[("bot", profile.bot), ("discoverable", profile.discoverable)]
+	{
+		if let Some(flag) = flag {
+			out.push((String::from(key), flag.to_string()));
+		}
+	}
+	out
+}
+
+// PATCH /api/v1/accounts/update_credentials
+/// Updates whichever parts of the account's profile have changed since
+/// it was last updated.
+fn update(
+	config: &Config,
+	secrets: &Secrets,
+	history: &History,
+) -> Result<()> {
+	let Some(profile) = &config.profile else {
+		return Ok(());
+	};
+	let mut applied = Applied::load(config)?;
+	let text = text(profile, history);
+	let text_hash = profile::hash(json!(text).to_string());
+	let text = match applied.changed("text", &text_hash) {
+		true => text,
+		false => Vec::new(),
+	};
+	let images =
+		[("avatar", &profile.avatar), ("header", &profile.header)]
+			.into_iter()
+			.filter_map(|(name, path)| Some((name, path.as_ref()?)))
+			.map(|(name, path)| Image::load(name, path))
+			.collect::<Result<Vec<_>>>()?
+			.into_iter()
+			.filter(|image| applied.changed(image.name, &image.hash))
+			.collect::<Vec<_>>();
+	if text.is_empty() && images.is_empty() {
+		return Ok(());
+	}
+
+	log::info!("Updating profile");
+	// a form can only be sent once, so it's built again for each attempt
+	let form = || {
+		let mut form = Form::new();
+		for (key, value) in &text {
+			form = form.text(key.clone(), value.clone());
+		}
+		for image in &images {
+			form = form.part(
+				image.name,
+				Part::bytes(image.data.clone())
+					.file_name(image.file_name.clone())
+					.mime_str(image.mime)
+					.expect("Profile images have valid MIME types."),
+			);
+		}
+		form
+	};
+	super::request::send(|client| {
+		client
+			.patch(super::url(
+				&config.instance,
+				"/api/v1/accounts/update_credentials",
+			))
+			.multipart(form())
+			.header("Authorization", &secrets.token)
+	})?
+	.error_for_status()
+	.context("Failed to update the profile.")?;
+
+	if !text.is_empty() {
+		applied.set("text", text_hash);
+	}
+	for image in images {
+		applied.set(image.name, image.hash);
+	}
+	applied.updated = Some(Utc::now());
+	applied.dump()
+}
+
+/// Brings the profiles of the account, and any it cross-posts to, up
+/// to date with the config. This is done on startup and after every
+/// post, so that counters in the bio stay current.
+pub fn tick<T, D>(cfg: &PostCfg<T, D>) -> Result<()>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	for mirror in &cfg.mirrors {
+		let config = mirror.config;
+		let Some(secrets) = mirror.secrets else {
+			continue;
+		};
+		if let Err(e) = update(config, secrets, &mirror.history) {
+			let name = config.name.as_deref().unwrap_or_default();
+			log::error!(
+				"{:?}",
+				e.context(format!(
+					"Failed to update {name}'s profile."
+				))
+			);
+		}
+	}
+	match cfg.secrets {
+		Some(secrets) => update(cfg.config, secrets, &cfg.history),
+		None => Ok(()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::data::history::Entry;
+
+	fn entry(inventor: &str, invention: &str) -> Entry {
+		Entry {
+			timestamp: Utc::now(),
+			inventor: inventor.to_owned(),
+			invention: invention.to_owned(),
+			template: String::new(),
+			status: format!("{inventor} invented {invention}"),
+			id: String::from("1"),
+			url: None,
+			visibility: None,
+			in_reply_to: None,
+			poll: None,
+			scheduled: false,
+			deleted: false,
+			rule: None,
+			source: None,
+			engagement: None,
+		}
+	}
+
+	#[test]
+	fn bio_from_published_posts() {
+		let profile = toml::from_str::<Profile>(
+			"bio = \"{count} so far, latest {inventor}: \
+			{invention}\"\n\
+			fields = [{ name = \"Code\", value = \"here\" }]\n\
+			bot = true\n",
+		)
+		.unwrap();
+		let mut history = History::default();
+		history.posts.push(entry("Ada", "the loom"));
+		history.posts.push(entry("Grace", "the compiler"));
+		// none of these are up on the account as posts
+		history.posts.push(Entry {
+			in_reply_to: Some(String::from("2")),
+			..entry("Alice", "the reply")
+		});
+		history.posts.push(Entry {
+			scheduled: true,
+			..entry("Bob", "tomorrow")
+		});
+		history.posts.push(Entry {
+			deleted: true,
+			..entry("Carol", "the eraser")
+		});
+		history.posts.push(Entry {
+			id: String::new(),
+			..entry("Dave", "the lost post")
+		});
+
+		let text = text(&profile, &history);
+		let value = |key: &str| {
+			text.iter()
+				.find(|(k, _)| k == key)
+				.map(|(_, v)| v.as_str())
+		};
+		assert_eq!(
+			value("note"),
+			Some("2 so far, latest Grace: the compiler")
+		);
+		assert_eq!(value("fields_attributes[0][name]"), Some("Code"));
+		// the rest are sent blank to clear them
+		assert_eq!(value("fields_attributes[3][value]"), Some(""));
+		assert_eq!(value("fields_attributes[4][name]"), None);
+		assert_eq!(value("bot"), Some("true"));
+		assert_eq!(value("discoverable"), None);
+	}
+
+	#[test]
+	fn bio_without_posts() {
+		let profile =
+			toml::from_str::<Profile>("bio = \"{count} so far\"")
+				.unwrap();
+		let text = text(&profile, &History::default());
+		assert_eq!(
+			text,
+			[(String::from("note"), String::from("0 so far"))]
+		);
+	}
+}
@@ -0,0 +1,301 @@
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Local, Utc};
+use rand::{distributions::Distribution, Rng, SeedableRng};
+use reqwest::StatusCode;
+
+use super::{misskey, post::PostCfg, stats};
+use crate::{
+	cli::PruneArgs,
+	data::{
+		config::{Backend, Config},
+		history::History,
+		secrets::Secrets,
+	},
+};
+
+/// The most posts deleted after each post is made. Mastodon only
+/// allows 30 deletions every half hour, so a long backlog is worked
+/// through a little at a time instead of waiting on the rate limit.
+const BATCH: usize = 5;
+/// How long a post spared for its favourites is left before they're
+/// counted again, so that it doesn't use up every batch.
+const RECHECK: Duration = Duration::days(1);
+
+// DELETE /api/v1/statuses/:id
+/// Deletes a post, which is fine if it's already gone.
+fn delete(
+	config: &Config,
+	secrets: &Secrets,
+	id: &str,
+) -> Result<()> {
+	let instance = &config.instance;
+	if config.backend == Backend::Misskey {
+		return misskey::delete(instance, secrets, id);
+	}
+	let response = super::request::send(|client| {
+		client
+			.delete(super::url(
+				instance,
+				&format!("/api/v1/statuses/{id}"),
+			))
+			.header("Authorization", &secrets.token)
+	})?;
+	if response.status() != StatusCode::NOT_FOUND {
+		response.error_for_status()?;
+	}
+	Ok(())
+}
+
+/// The posts in the history which are too old to keep, oldest first.
+/// Scheduled posts are left alone, since only the id they were
+/// scheduled with is known, not the id of the post that was made.
+fn expired(config: &Config, history: &History) -> Vec<usize> {
+	let retention = &config.retention;
+	let cutoff = retention
+		.days
+		.map(|days| Utc::now() - Duration::days(days as i64));
+	let mut out = history
+		.posts
+		.iter()
+		.enumerate()
+		.rev()
+		.filter(|(_, entry)| {
+			!entry.deleted && !entry.scheduled && !entry.id.is_empty()
+		})
+		// counted from the newest post
+		.enumerate()
+		.filter(|(n, (_, entry))| {
+			let by_count =
+				retention.keep.is_some_and(|keep| *n >= keep);
+			let by_days =
+				cutoff.is_some_and(|cutoff| entry.timestamp < cutoff);
+			by_count || by_days
+		})
+		.map(|(_, (i, _))| i)
+		.collect::<Vec<_>>();
+	out.reverse();
+	out
+}
+
+/// Deletes up to `limit` posts the retention policy says should go,
+/// along with the results of any polls on them, and returns how many
+/// were deleted. No more than `limit` posts have their favourites
+/// looked up either, and with a limit, it stops early if the instance
+/// is rate limiting. With `list`, they're printed instead.
+fn prune(
+	config: &Config,
+	secrets: Option<&Secrets>,
+	history: &mut History,
+	limit: Option<usize>,
+	list: bool,
+) -> Result<usize> {
+	let spare = config.retention.favourites;
+	if let (Some(_), None) = (spare, secrets) {
+		log::warn!("Not logged in, so favourites can't be checked.");
+	}
+	let now = Utc::now();
+	let mut count = 0;
+	let mut lookups = 0;
+	for i in expired(config, history) {
+		if limit.is_some_and(|limit| count.max(lookups) >= limit) {
+			break;
+		}
+		if limit.is_some()
+			&& super::request::limited(&config.instance)
+		{
+			log::info!(
+				"Rate limited, so old posts will be deleted later."
+			);
+			break;
+		}
+		let entry = &history.posts[i];
+		if let (Some(spare), Some(secrets)) = (spare, secrets) {
+			let spared = entry.engagement.as_ref().is_some_and(|e| {
+				e.favourites > spare && now - e.checked < RECHECK
+			});
+			if spared {
+				log::debug!("Still keeping {}.", entry.id);
+				continue;
+			}
+			lookups += 1;
+			match stats::fetch(config, secrets, &entry.id) {
+				Ok(Some(e)) if e.favourites > spare => {
+					log::info!(
+						"Keeping {}, which has {} favourites.",
+						entry.id,
+						e.favourites
+					);
+					history.posts[i].engagement = Some(e);
+					history.dump()?;
+					continue;
+				},
+				Ok(Some(_)) => {},
+				Ok(None) => {
+					log::info!(
+						"{} has already been deleted.",
+						entry.id
+					);
+					history.posts[i].deleted = true;
+					history.dump()?;
+					continue;
+				},
+				Err(e) => {
+					log::error!(
+						"Failed to check the favourites of {}: {e}",
+						entry.id
+					);
+					continue;
+				},
+			}
+		}
+		if list {
+			println!(
+				"[{}] {}: {}",
+				entry
+					.timestamp
+					.with_timezone(&Local)
+					.format("%Y-%m-%d %H:%M"),
+				entry.id,
+				entry.status
+			);
+			count += 1;
+			continue;
+		}
+		let secrets = secrets.ok_or(anyhow!("Not authorised."))?;
+		log::info!("Deleting {}", entry.id);
+		delete(config, secrets, &entry.id)?;
+		if let Some(result) =
+			entry.poll.as_ref().and_then(|p| p.result.as_ref())
+		{
+			delete(config, secrets, result)?;
+		}
+		history.posts[i].deleted = true;
+		history.dump()?;
+		count += 1;
+	}
+	Ok(count)
+}
+
+/// Deletes a few old posts from the account and any it cross-posts
+/// to, which is done after every post.
+pub fn tick<T, D>(cfg: &mut PostCfg<T, D>) -> Result<()>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	for mirror in &mut cfg.mirrors {
+		let config = mirror.config;
+		if !config.retention.enabled() || config.dry_run {
+			continue;
+		}
+		let pruned = prune(
+			config,
+			mirror.secrets,
+			&mut mirror.history,
+			Some(BATCH),
+			false,
+		);
+		if let Err(e) = pruned {
+			let name = config.name.as_deref().unwrap_or_default();
+			log::error!(
+				"{:?}",
+				e.context(format!("Failed to prune {name}."))
+			);
+		}
+	}
+	let config = cfg.config;
+	if !config.retention.enabled() || config.dry_run {
+		return Ok(());
+	}
+	prune(config, cfg.secrets, &mut cfg.history, Some(BATCH), false)?;
+	Ok(())
+}
+
+/// Deletes every post the retention policy says should go, from the
+/// account and any it cross-posts to.
+pub fn run<T, D>(
+	cfg: &mut PostCfg<T, D>,
+	args: &PruneArgs,
+) -> Result<()>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	let mut accounts =
+		vec![(cfg.config, cfg.secrets, &mut cfg.history)];
+	for mirror in &mut cfg.mirrors {
+		accounts.push((
+			mirror.config,
+			mirror.secrets,
+			&mut mirror.history,
+		));
+	}
+	let several = accounts.len() > 1;
+	for (config, secrets, history) in accounts {
+		if let (Some(name), true) = (&config.name, several) {
+			println!("{name}:");
+		}
+		if !config.retention.enabled() {
+			println!("Nothing to prune, `retention` isn't set.");
+			continue;
+		}
+		let list = args.list || config.dry_run;
+		let count = prune(config, secrets, history, None, list)?;
+		match list {
+			true => println!("{count} posts would be deleted."),
+			false => println!("Deleted {count} posts."),
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::data::history::Entry;
+
+	/// A post made `days` ago, with anything else about it in `rest`.
+	fn post(id: &str, days: i64, rest: &str) -> Entry {
+		toml::from_str(&format!(
+			"timestamp = {:?}\nid = {id:?}\n\
+			inventor = \"\"\ninvention = \"\"\ntemplate = \"\"\n\
+			status = \"\"\n{rest}",
+			(Utc::now() - Duration::days(days)).to_rfc3339()
+		))
+		.unwrap()
+	}
+
+	fn retention(rules: &str) -> Config {
+		toml::from_str(&format!(
+			"instance = \"example.com\"\ncache.path = \"/cache\"\n\
+			[retention]\n{rules}"
+		))
+		.unwrap()
+	}
+
+	#[test]
+	fn expired_posts() {
+		let mut history = History::default();
+		history.posts = vec![
+			post("1", 40, ""),
+			post("2", 35, "deleted = true\n"),
+			post("3", 31, "scheduled = true\n"),
+			post("", 30, ""),
+			post("5", 20, ""),
+			post("6", 10, ""),
+			post("7", 1, ""),
+		];
+		let expired =
+			|rules: &str| expired(&retention(rules), &history);
+		assert_eq!(expired(""), Vec::<usize>::new());
+		// only posts that are still up, and whose id is known, count
+		assert_eq!(expired("days = 25\n"), [0]);
+		assert_eq!(expired("days = 15\n"), [0, 4]);
+		assert_eq!(expired("keep = 2\n"), [0, 4]);
+		assert_eq!(expired("keep = 3\n"), [0]);
+		assert_eq!(expired("keep = 0\n"), [0, 4, 5, 6]);
+		// either rule is enough for a post to go
+		assert_eq!(expired("days = 15\nkeep = 3\n"), [0, 4]);
+		assert_eq!(expired("days = 35\nkeep = 2\n"), [0, 4]);
+	}
+}
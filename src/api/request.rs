@@ -0,0 +1,283 @@
+use std::{
+	collections::BTreeMap, sync::Mutex, thread, time::Duration,
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::{
+	blocking::{Client, Request, RequestBuilder, Response},
+	header::HeaderMap,
+	Method, StatusCode,
+};
+
+const ATTEMPTS: u32 = 5;
+const BASE_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(60);
+// Waiting any longer than this for a rate limit is probably a
+// mistake.
+const MAX_WAIT: Duration = Duration::from_secs(15 * 60);
+
+/// When each host said we can make requests again, after running out.
+static LIMITED_UNTIL: Mutex<BTreeMap<String, DateTime<Utc>>> =
+	Mutex::new(BTreeMap::new());
+
+/// Sends a request, retrying with exponential backoff if it fails to
+/// send, or the instance is rate limiting or having trouble.
+///
+/// `make` is called to build the request again for every attempt, so
+/// it must build exactly the same request each time, including any
+/// `Idempotency-Key`, so that a post which got through but whose
+/// response was lost isn't made twice. Requests that aren't safe to
+/// repeat are only retried when they couldn't connect at all.
+pub fn send(
+	make: impl Fn(&Client) -> RequestBuilder,
+) -> Result<Response> {
+	attempt(make, ATTEMPTS)
+}
+
+/// Sends a request without retrying, for when there's something
+/// better to do than wait if it fails.
+pub fn send_once(
+	make: impl Fn(&Client) -> RequestBuilder,
+) -> Result<Response> {
+	attempt(make, 1)
+}
+
+fn attempt(
+	make: impl Fn(&Client) -> RequestBuilder,
+	attempts: u32,
+) -> Result<Response> {
+	let client = super::RQ_CLIENT.get().unwrap();
+	let mut attempt = 0;
+	loop {
+		let request =
+			make(client).build().context("Invalid request.")?;
+		let host = request.url().host_str().unwrap_or("").to_owned();
+		let repeatable = idempotent(&request);
+		wait_for_limit(&host);
+		attempt += 1;
+		let delay = match client.execute(request) {
+			Ok(response) => {
+				record_limit(&host, response.headers());
+				let status = response.status();
+				if !repeatable
+					|| !retryable(status)
+					|| attempt >= attempts
+				{
+					return Ok(response);
+				}
+				log::warn!(
+					"Request to {} failed with {status}.",
+					response.url().path()
+				);
+				retry_after(response.headers())
+					.unwrap_or_else(|| backoff(attempt))
+			},
+			// nothing was sent if it couldn't connect
+			Err(e)
+				if attempt < attempts
+					&& (repeatable || e.is_connect()) =>
+			{
+				log::warn!("Request failed: {e}");
+				backoff(attempt)
+			},
+			Err(e) => return Err(e).context("Request failed."),
+		};
+		log::info!(
+			"Retrying in {:.1} seconds ({attempt}/{attempts}).",
+			delay.as_secs_f32()
+		);
+		thread::sleep(delay);
+	}
+}
+
+/// Whether sending the request twice does the same as sending it once.
+/// Posts are made so by their `Idempotency-Key`. `PATCH` isn't, since
+/// updating the profile uploads its images again every time.
+fn idempotent(request: &Request) -> bool {
+	matches!(
+		*request.method(),
+		Method::GET | Method::PUT | Method::DELETE
+	) || request.headers().contains_key("Idempotency-Key")
+}
+
+fn retryable(status: StatusCode) -> bool {
+	matches!(
+		status,
+		StatusCode::TOO_MANY_REQUESTS
+			| StatusCode::INTERNAL_SERVER_ERROR
+			| StatusCode::BAD_GATEWAY
+			| StatusCode::SERVICE_UNAVAILABLE
+			| StatusCode::GATEWAY_TIMEOUT
+	)
+}
+
+/// Exponential backoff with full jitter, so that lots of bots on one
+/// instance don't all retry at once.
+fn backoff(attempt: u32) -> Duration {
+	let max = BASE_DELAY
+		.saturating_mul(2_u32.saturating_pow(attempt - 1))
+		.min(MAX_DELAY);
+	max.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+	headers.get(name)?.to_str().ok()
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+	let value = header(headers, "Retry-After")?;
+	if let Ok(seconds) = value.trim().parse::<u64>() {
+		return Some(Duration::from_secs(seconds).min(MAX_WAIT));
+	}
+	let at = DateTime::parse_from_rfc2822(value).ok()?;
+	Some(until(at.with_timezone(&Utc)))
+}
+
+fn until(at: DateTime<Utc>) -> Duration {
+	(at - Utc::now()).to_std().unwrap_or_default().min(MAX_WAIT)
+}
+
+/// Mastodon says how many requests are left with
+/// `X-RateLimit-Remaining`, and when that resets with
+/// `X-RateLimit-Reset`. Responses without them, like errors from a
+/// proxy, don't say anything about the limit.
+fn record_limit(host: &str, headers: &HeaderMap) {
+	let remaining = header(headers, "X-RateLimit-Remaining")
+		.and_then(|r| r.parse::<u64>().ok());
+	let reset = header(headers, "X-RateLimit-Reset")
+		.and_then(|r| DateTime::parse_from_rfc3339(r).ok());
+	let mut limited = LIMITED_UNTIL.lock().unwrap();
+	match (remaining, reset) {
+		(Some(0), Some(reset)) => {
+			limited
+				.insert(host.to_owned(), reset.with_timezone(&Utc));
+		},
+		(Some(_), _) => {
+			limited.remove(host);
+		},
+		(None, _) => {},
+	}
+}
+
+/// Whether the instance has said no more requests can be made for
+/// now, so that anything which can wait until later doesn't wait on
+/// the limit instead.
+pub fn limited(instance: &str) -> bool {
+	let url = super::url(instance, "/");
+	let Some(host) = reqwest::Url::parse(&url)
+		.ok()
+		.and_then(|url| url.host_str().map(str::to_owned))
+	else {
+		return false;
+	};
+	LIMITED_UNTIL
+		.lock()
+		.unwrap()
+		.get(&host)
+		.is_some_and(|at| *at > Utc::now())
+}
+
+fn wait_for_limit(host: &str) {
+	let Some(at) = LIMITED_UNTIL.lock().unwrap().remove(host) else {
+		return;
+	};
+	let wait = until(at);
+	if !wait.is_zero() {
+		log::warn!(
+			"Rate limited by the instance, waiting {} seconds.",
+			wait.as_secs()
+		);
+		thread::sleep(wait);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use reqwest::header::HeaderValue;
+
+	use super::*;
+
+	fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+		let mut headers = HeaderMap::new();
+		for (name, value) in pairs {
+			headers
+				.insert(*name, HeaderValue::from_str(value).unwrap());
+		}
+		headers
+	}
+
+	#[test]
+	fn retry_after_seconds() {
+		let wait = retry_after(&headers(&[("Retry-After", "120")]));
+		assert_eq!(wait, Some(Duration::from_secs(120)));
+		// asking for too long a wait is capped
+		let wait = retry_after(&headers(&[("Retry-After", "86400")]));
+		assert_eq!(wait, Some(MAX_WAIT));
+		assert_eq!(retry_after(&headers(&[])), None);
+		assert_eq!(
+			retry_after(&headers(&[("Retry-After", "soon")])),
+			None
+		);
+	}
+
+	#[test]
+	fn retry_after_date() {
+		let at = Utc::now() + chrono::Duration::seconds(90);
+		let wait = retry_after(&headers(&[(
+			"Retry-After",
+			&at.to_rfc2822(),
+		)]))
+		.unwrap();
+		assert!(wait > Duration::from_secs(80), "{wait:?}");
+		assert!(wait <= Duration::from_secs(90), "{wait:?}");
+		// dates that have already passed don't wait at all
+		let wait = retry_after(&headers(&[(
+			"Retry-After",
+			"Wed, 21 Oct 2015 07:28:00 GMT",
+		)]));
+		assert_eq!(wait, Some(Duration::ZERO));
+	}
+
+	#[test]
+	fn rate_limit_reset() {
+		let host = "limits.test";
+		let reset = Utc::now() + chrono::Duration::minutes(5);
+		let limit = |remaining: &str| {
+			headers(&[
+				("X-RateLimit-Remaining", remaining),
+				("X-RateLimit-Reset", &reset.to_rfc3339()),
+			])
+		};
+		let stored =
+			|| LIMITED_UNTIL.lock().unwrap().get(host).copied();
+		record_limit(host, &limit("3"));
+		assert_eq!(stored(), None);
+		record_limit(host, &limit("0"));
+		assert_eq!(
+			stored().map(|at| at.timestamp()),
+			Some(reset.timestamp())
+		);
+		// a response without the headers leaves the limit alone
+		record_limit(host, &headers(&[]));
+		assert!(stored().is_some());
+		record_limit(host, &limit("299"));
+		assert_eq!(stored(), None);
+	}
+
+	#[test]
+	fn backoff_is_capped() {
+		for attempt in 1..=ATTEMPTS {
+			let max = BASE_DELAY * 2_u32.pow(attempt - 1);
+			let delay = backoff(attempt);
+			assert!(delay <= max && delay >= max / 2, "{delay:?}");
+		}
+		for attempt in [10, 40, u32::MAX] {
+			let delay = backoff(attempt);
+			assert!(delay <= MAX_DELAY, "{delay:?}");
+			assert!(delay >= MAX_DELAY / 2, "{delay:?}");
+		}
+	}
+}
@@ -0,0 +1,194 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Local, Utc};
+use rand::{distributions::Distribution, Rng, SeedableRng};
+use serde::Deserialize;
+
+use super::post::{self, PostCfg};
+use crate::{
+	cli::{CancelArgs, ScheduleAction, ScheduleArgs},
+	data::{config::Backend, secrets::Secrets},
+};
+
+// Mastodon won't schedule anything less than 5 minutes away, so leave
+// some room for the requests to be made.
+const MIN_DELAY: Duration = Duration::minutes(6);
+// The most scheduled statuses Mastodon will return at once.
+const PAGE: usize = 40;
+
+#[derive(Deserialize)]
+struct Params {
+	text: String,
+}
+
+#[derive(Deserialize)]
+struct Scheduled {
+	id: String,
+	scheduled_at: DateTime<Utc>,
+	params: Params,
+}
+
+// GET /api/v1/scheduled_statuses
+/// Fetches every scheduled status, soonest first.
+fn fetch(
+	instance: &str,
+	secrets: &Secrets,
+) -> Result<Vec<Scheduled>> {
+	let mut out = Vec::<Scheduled>::new();
+	loop {
+		let mut query = vec![("limit", PAGE.to_string())];
+		if let Some(last) = out.last() {
+			query.push(("max_id", last.id.clone()));
+		}
+		let text = super::request::send(|client| {
+			client
+				.get(super::url(
+					instance,
+					"/api/v1/scheduled_statuses",
+				))
+				.query(&query)
+				.header("Authorization", &secrets.token)
+		})?
+		.error_for_status()?
+		.text()?;
+		let page = serde_json::from_str::<Vec<Scheduled>>(&text)?;
+		let done = page.len() < PAGE;
+		out.extend(page);
+		if done {
+			out.sort_by_key(|s| s.scheduled_at);
+			return Ok(out);
+		}
+	}
+}
+
+// DELETE /api/v1/scheduled_statuses/:id
+fn delete(instance: &str, secrets: &Secrets, id: &str) -> Result<()> {
+	super::request::send(|client| {
+		client
+			.delete(super::url(
+				instance,
+				&format!("/api/v1/scheduled_statuses/{id}"),
+			))
+			.header("Authorization", &secrets.token)
+	})?
+	.error_for_status()?;
+	Ok(())
+}
+
+/// Schedules posts after any that are already scheduled.
+fn add<T, D>(
+	cfg: &mut PostCfg<T, D>,
+	args: &ScheduleArgs,
+) -> Result<()>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	let config = cfg.config;
+	let every = args.every.or(config.repeat).ok_or(anyhow!(
+		"No time between posts was given. \
+		Either pass `--every` or set `repeat` in the config."
+	))?;
+	let every = Duration::minutes(every as i64);
+	let earliest = Utc::now() + MIN_DELAY;
+	let latest = match (config.dry_run, cfg.secrets) {
+		(false, Some(secrets)) => fetch(&config.instance, secrets)?
+			.last()
+			.map(|s| s.scheduled_at),
+		_ => None,
+	};
+	let mut at = latest
+		.map_or(earliest, |latest| (latest + every).max(earliest));
+	for _ in 0..args.count {
+		post::schedule(cfg, at)?;
+		at += every;
+	}
+	Ok(())
+}
+
+fn list<T, D>(cfg: &PostCfg<T, D>) -> Result<()>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	if cfg.config.dry_run {
+		return Err(anyhow!(
+			"Scheduled posts can't be listed on a dry run, since \
+			that needs logging in."
+		));
+	}
+	let secrets = cfg.secrets.ok_or(anyhow!("Not authorised."))?;
+	for scheduled in fetch(&cfg.config.instance, secrets)? {
+		println!(
+			"[{}] {}: {}",
+			scheduled
+				.scheduled_at
+				.with_timezone(&Local)
+				.format("%Y-%m-%d %H:%M"),
+			scheduled.id,
+			scheduled.params.text
+		);
+	}
+	Ok(())
+}
+
+fn cancel<T, D>(
+	cfg: &mut PostCfg<T, D>,
+	args: &CancelArgs,
+) -> Result<()>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	if cfg.config.dry_run {
+		if args.all {
+			return Err(anyhow!(
+				"`--all` can't be used on a dry run, since finding \
+				the scheduled posts needs logging in."
+			));
+		}
+		for id in &args.ids {
+			println!("Would cancel scheduled post {id}");
+		}
+		return Ok(());
+	}
+	let secrets = cfg.secrets.ok_or(anyhow!("Not authorised."))?;
+	let instance = &cfg.config.instance;
+	let ids = match args.all {
+		true => fetch(instance, secrets)?
+			.into_iter()
+			.map(|s| s.id)
+			.collect(),
+		false => args.ids.clone(),
+	};
+	for id in &ids {
+		log::info!("Cancelling scheduled post {id}");
+		delete(instance, secrets, id)?;
+		cfg.history
+			.posts
+			.retain(|entry| !(entry.scheduled && entry.id == *id));
+		cfg.history.dump()?;
+	}
+	println!("Cancelled {} scheduled posts.", ids.len());
+	Ok(())
+}
+
+pub fn run<T, D>(
+	cfg: &mut PostCfg<T, D>,
+	action: &ScheduleAction,
+) -> Result<()>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	if cfg.config.backend != Backend::Mastodon {
+		return Err(anyhow!(
+			"Scheduling isn't supported on {:?}.",
+			cfg.config.backend
+		));
+	}
+	match action {
+		ScheduleAction::Add(args) => add(cfg, args),
+		ScheduleAction::List => list(cfg),
+		ScheduleAction::Cancel(args) => cancel(cfg, args),
+	}
+}
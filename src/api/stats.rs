@@ -0,0 +1,378 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Utc};
+use rand::{
+	distributions::{Distribution, WeightedIndex},
+	Rng, SeedableRng,
+};
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+use super::{bluesky, misskey, post::PostCfg};
+use crate::data::{
+	config::{Backend, Config},
+	history::{Engagement, Entry, History},
+	secrets::Secrets,
+};
+
+/// The most posts checked after each post is made, so that a long
+/// history doesn't hold up the next post.
+const BATCH: usize = 20;
+
+// GET /api/v1/statuses/:id
+/// How people have interacted with a post, or `None` if it's gone.
+pub fn fetch(
+	config: &Config,
+	secrets: &Secrets,
+	id: &str,
+) -> Result<Option<Engagement>> {
+	#[derive(Deserialize)]
+	struct Status {
+		favourites_count: u64,
+		reblogs_count: u64,
+		replies_count: u64,
+	}
+	let instance = &config.instance;
+	match config.backend {
+		Backend::Mastodon => {},
+		Backend::Misskey => {
+			return misskey::engagement(instance, secrets, id)
+		},
+		Backend::Bluesky => {
+			return bluesky::engagement(config, secrets, id)
+		},
+	}
+	let response = super::request::send(|client| {
+		client
+			.get(super::url(
+				instance,
+				&format!("/api/v1/statuses/{id}"),
+			))
+			.header("Authorization", &secrets.token)
+	})?;
+	if response.status() == StatusCode::NOT_FOUND {
+		return Ok(None);
+	}
+	let text = response.error_for_status()?.text()?;
+	let status = serde_json::from_str::<Status>(&text)?;
+	Ok(Some(Engagement {
+		favourites: status.favourites_count,
+		reblogs: status.reblogs_count,
+		replies: status.replies_count,
+		checked: Utc::now(),
+	}))
+}
+
+/// Whether a post counts towards the stats. Replies to mentions and
+/// scheduled posts don't, nor do posts from before word sources
+/// were kept track of.
+fn counted(entry: &Entry) -> bool {
+	entry.in_reply_to.is_none()
+		&& !entry.scheduled
+		&& !entry.id.is_empty()
+		&& entry.source.is_some()
+}
+
+/// Checks up to `limit` recent posts that haven't been checked in a
+/// while, returning how many were.
+fn refresh(
+	config: &Config,
+	secrets: &Secrets,
+	history: &mut History,
+	limit: usize,
+) -> Result<usize> {
+	let stats = &config.stats;
+	let now = Utc::now();
+	let cutoff = now - Duration::days(stats.days as i64);
+	let stale = now - Duration::hours(stats.hours as i64);
+	let due = history
+		.posts
+		.iter()
+		.enumerate()
+		.rev()
+		.filter(|(_, entry)| {
+			counted(entry)
+				&& !entry.deleted
+				&& entry.timestamp >= cutoff
+				&& entry
+					.engagement
+					.as_ref()
+					.is_none_or(|e| e.checked < stale)
+		})
+		.map(|(i, _)| i)
+		.take(limit)
+		.collect::<Vec<_>>();
+	for &i in &due {
+		let entry = &mut history.posts[i];
+		match fetch(config, secrets, &entry.id) {
+			Ok(Some(engagement)) => {
+				entry.engagement = Some(engagement)
+			},
+			Ok(None) => {
+				log::info!("{} has been deleted.", entry.id);
+				entry.deleted = true;
+			},
+			Err(e) => log::error!(
+				"Failed to check how {} is doing: {e}",
+				entry.id
+			),
+		}
+	}
+	if !due.is_empty() {
+		history.dump()?;
+	}
+	Ok(due.len())
+}
+
+/// The posts, and their engagement, of a group of posts.
+#[derive(Default)]
+struct Row {
+	posts: u64,
+	favourites: u64,
+	reblogs: u64,
+	replies: u64,
+}
+
+impl Row {
+	fn add(&mut self, engagement: &Engagement) {
+		self.posts += 1;
+		self.favourites += engagement.favourites;
+		self.reblogs += engagement.reblogs;
+		self.replies += engagement.replies;
+	}
+
+	/// The average engagement of each post.
+	fn score(&self) -> f64 {
+		let total = self.favourites + self.reblogs + self.replies;
+		total as f64 / self.posts.max(1) as f64
+	}
+}
+
+/// Adds up the engagement of every checked post, grouped by `key`.
+fn tally(
+	history: &History,
+	key: impl Fn(&Entry) -> String,
+) -> (BTreeMap<String, Row>, Row) {
+	let mut rows = BTreeMap::<String, Row>::new();
+	let mut all = Row::default();
+	for entry in history.posts.iter().filter(|e| counted(e)) {
+		let Some(engagement) = &entry.engagement else {
+			continue;
+		};
+		rows.entry(key(entry)).or_default().add(engagement);
+		all.add(engagement);
+	}
+	(rows, all)
+}
+
+/// How much to weight each inventor by, from how well their posts
+/// have done compared to everyone's, never going below `stats.floor`
+/// of their weight.
+fn weights(config: &Config, history: &History) -> Vec<f64> {
+	let (rows, all) =
+		tally(history, |entry| entry.persona().to_lowercase());
+	let average = all.score();
+	config
+		.inventors
+		.iter()
+		.map(|inventor| {
+			let row = rows.get(&inventor.name.to_lowercase());
+			let boost = match row {
+				Some(row) if average > 0.0 => row.score() / average,
+				_ => 1.0,
+			};
+			inventor.weight as f64 * boost.max(config.stats.floor)
+		})
+		.collect()
+}
+
+/// Weights each inventor by how well their posts have done, if
+/// `stats.adaptive` is on.
+pub fn adapt<T, D>(cfg: &mut PostCfg<T, D>) -> Result<()>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	let config = cfg.config;
+	if !config.stats.adaptive {
+		return Ok(());
+	}
+	let weights = weights(config, &cfg.history);
+	log::info!(
+		"Inventor weights from engagement: {}",
+		config
+			.inventors
+			.iter()
+			.zip(&weights)
+			.map(|(inventor, weight)| {
+				format!("{} {weight:.2}", inventor.name)
+			})
+			.collect::<Vec<_>>()
+			.join(", ")
+	);
+	cfg.adaptive = Some(
+		WeightedIndex::new(weights)
+			.map_err(|e| anyhow!("Invalid adaptive weights: {e}"))?,
+	);
+	Ok(())
+}
+
+/// Checks how a few recent posts are doing, on the account and any
+/// it cross-posts to, which is done after every post. Inventors are
+/// then weighted by the new numbers.
+pub fn tick<T, D>(cfg: &mut PostCfg<T, D>) -> Result<()>
+where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	for mirror in &mut cfg.mirrors {
+		let config = mirror.config;
+		let Some(secrets) = mirror.secrets else {
+			continue;
+		};
+		if !config.stats.enable {
+			continue;
+		}
+		let refreshed =
+			refresh(config, secrets, &mut mirror.history, BATCH);
+		if let Err(e) = refreshed {
+			let name = config.name.as_deref().unwrap_or_default();
+			log::error!(
+				"{:?}",
+				e.context(format!("Failed to check {name}'s posts."))
+			);
+		}
+	}
+	let config = cfg.config;
+	let Some(secrets) = cfg.secrets else {
+		return Ok(());
+	};
+	if !config.stats.enable {
+		return Ok(());
+	}
+	refresh(config, secrets, &mut cfg.history, BATCH)?;
+	adapt(cfg)
+}
+
+fn table(title: &str, rows: BTreeMap<String, Row>) {
+	let mut rows = rows.into_iter().collect::<Vec<_>>();
+	rows.sort_by(|(_, a), (_, b)| b.score().total_cmp(&a.score()));
+	let width = rows
+		.iter()
+		.map(|(name, _)| name.chars().count())
+		.chain([title.len()])
+		.max()
+		.unwrap_or_default();
+	println!(
+		"{title:<width$}  {:>5}  {:>10}  {:>7}  {:>7}",
+		"posts", "favourites", "reblogs", "replies"
+	);
+	for (name, row) in rows {
+		let average = |n: u64| n as f64 / row.posts as f64;
+		println!(
+			"{name:<width$}  {:>5}  {:>10.1}  {:>7.1}  {:>7.1}",
+			row.posts,
+			average(row.favourites),
+			average(row.reblogs),
+			average(row.replies)
+		);
+	}
+}
+
+/// Prints which inventors and word sources do best, with the average
+/// engagement of each of their posts.
+pub fn report(history: &History) {
+	let (inventors, all) =
+		tally(history, |entry| entry.persona().to_owned());
+	if all.posts == 0 {
+		println!("No posts have been checked yet.");
+		return;
+	}
+	table("inventor", inventors);
+	println!();
+	let (sources, _) = tally(history, |entry| {
+		format!("{:?}", entry.source.unwrap_or_default())
+			.to_lowercase()
+	});
+	table("source", sources);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A post by `inventor` with `likes` favourites.
+	fn post(inventor: &str, likes: u64) -> Entry {
+		toml::from_str(&format!(
+			"timestamp = {now:?}\nid = \"1\"\nsource = \"bundled\"\n\
+			inventor = {inventor:?}\ninvention = \"\"\n\
+			template = \"\"\nstatus = \"\"\n\
+			[engagement]\nfavourites = {likes}\nreblogs = 0\n\
+			replies = 0\nchecked = {now:?}\n",
+			now = Utc::now().to_rfc3339()
+		))
+		.unwrap()
+	}
+
+	#[test]
+	fn tallies() {
+		let mut history = History::default();
+		history.posts = vec![
+			post("Ada", 3),
+			post("Ada", 5),
+			post("Grace", 2),
+			// none of these count
+			Entry {
+				in_reply_to: Some(String::from("2")),
+				..post("Grace", 9)
+			},
+			Entry {
+				scheduled: true,
+				..post("Grace", 9)
+			},
+			Entry {
+				id: String::new(),
+				..post("Grace", 9)
+			},
+			Entry {
+				source: None,
+				..post("Grace", 9)
+			},
+			Entry {
+				engagement: None,
+				..post("Grace", 9)
+			},
+		];
+		let (rows, all) = tally(&history, |e| e.inventor.clone());
+		assert_eq!(rows.len(), 2);
+		let ada = &rows["Ada"];
+		assert_eq!((ada.posts, ada.favourites), (2, 8));
+		assert_eq!(ada.score(), 4.0);
+		assert_eq!(rows["Grace"].score(), 2.0);
+		assert_eq!(all.posts, 3);
+		assert_eq!(all.score(), 10.0 / 3.0);
+		assert_eq!(Row::default().score(), 0.0);
+	}
+
+	#[test]
+	fn adapts_weights() {
+		let config = toml::from_str::<Config>(
+			"instance = \"example.com\"\ncache.path = \"/cache\"\n\
+			inventors = [\"Ada\", { name = \"Grace\", weight = 2 }, \
+			\"Alan\"]\n\
+			stats.floor = 0.5\n",
+		)
+		.unwrap();
+		// with nothing to go on, every inventor keeps their weight
+		let mut history = History::default();
+		assert_eq!(weights(&config, &history), [1.0, 2.0, 1.0]);
+
+		history.posts =
+			vec![post("ada", 6), post("Grace", 0), post("Grace", 0)];
+		// Ada's posts do three times as well as average, Grace's
+		// don't do well at all so only go down to the floor, and Alan
+		// has no posts yet
+		assert_eq!(weights(&config, &history), [3.0, 1.0, 1.0]);
+	}
+}
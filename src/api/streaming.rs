@@ -0,0 +1,274 @@
+use std::{
+	io::{BufRead, BufReader},
+	sync::mpsc::Sender,
+	thread,
+	time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+
+// Reconnecting backs off exponentially between these.
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Something the main loop should react to.
+#[derive(Debug)]
+pub enum Event {
+	/// Ctrl-C was pressed.
+	Exit,
+	/// The stream is (re)connected, so polling isn't needed.
+	Connected,
+	/// The stream dropped, so fall back to polling until it's back.
+	Disconnected,
+	Mention {
+		acct: String,
+	},
+	Follow {
+		acct: String,
+	},
+	Favourite {
+		acct: String,
+		status: String,
+	},
+}
+
+#[derive(Deserialize)]
+struct Account {
+	acct: String,
+}
+
+#[derive(Deserialize)]
+struct Status {
+	id: String,
+}
+
+#[derive(Deserialize)]
+struct Notification {
+	#[serde(rename = "type")]
+	kind: String,
+	account: Account,
+	status: Option<Status>,
+}
+
+// GET /api/v1/instance
+/// The streaming API can live on a different host to the rest of the
+/// API, and is advertised by the instance.
+fn base(instance: &str) -> String {
+	#[derive(Deserialize)]
+	struct Urls {
+		streaming_api: String,
+	}
+	#[derive(Deserialize)]
+	struct Response {
+		urls: Urls,
+	}
+	let found = super::request::send(|client| {
+		client.get(super::url(instance, "/api/v1/instance"))
+	})
+	.ok()
+	.and_then(|r| r.error_for_status().ok())
+	.and_then(|r| r.text().ok())
+	.and_then(|text| serde_json::from_str::<Response>(&text).ok());
+	match found {
+		Some(response) => response
+			.urls
+			.streaming_api
+			.replacen("wss://", "https://", 1)
+			.replacen("ws://", "http://", 1),
+		None => super::url(instance, ""),
+	}
+}
+
+/// Turns a notification into an event, if it's one we care about.
+fn event(data: &str) -> Result<Option<Event>> {
+	let notification = serde_json::from_str::<Notification>(data)?;
+	let acct = notification.account.acct;
+	Ok(match notification.kind.as_str() {
+		"mention" => Some(Event::Mention { acct }),
+		"follow" => Some(Event::Follow { acct }),
+		"favourite" => {
+			notification.status.map(|status| Event::Favourite {
+				acct,
+				status: status.id,
+			})
+		},
+		_ => None,
+	})
+}
+
+// GET /api/v1/streaming/user/notification
+/// Reads server-sent events until the connection drops.
+fn stream(
+	client: &Client,
+	url: &str,
+	token: &str,
+	send: &Sender<Event>,
+	backoff: &mut Duration,
+) -> Result<()> {
+	let response = client
+		.get(format!("{url}/api/v1/streaming/user/notification"))
+		.header("Authorization", token)
+		.header("Accept", "text/event-stream")
+		.send()?
+		.error_for_status()
+		.context("Failed to connect to the streaming API.")?;
+	log::info!("Connected to the streaming API.");
+	*backoff = MIN_BACKOFF;
+	let _ = send.send(Event::Connected);
+
+	let mut name = String::new();
+	let mut data = String::new();
+	for line in BufReader::new(response).lines() {
+		let line = line?;
+		if line.is_empty() {
+			// a blank line ends an event
+			if name == "notification" {
+				match event(&data) {
+					Ok(Some(event)) => {
+						if send.send(event).is_err() {
+							return Ok(());
+						}
+					},
+					Ok(None) => {},
+					Err(e) => log::warn!("Bad notification: {e}"),
+				}
+			}
+			name.clear();
+			data.clear();
+		} else if let Some(value) = line.strip_prefix("event:") {
+			name = value.trim().to_owned();
+		} else if let Some(value) = line.strip_prefix("data:") {
+			if !data.is_empty() {
+				data.push('\n');
+			}
+			data.push_str(value.trim_start());
+		}
+		// anything else, like `:thump` heartbeats, is ignored
+	}
+	Err(anyhow!("The streaming API closed the connection."))
+}
+
+/// Streams notifications on a background thread, reconnecting whenever
+/// the connection drops.
+pub fn spawn(
+	instance: &str,
+	token: &str,
+	send: Sender<Event>,
+) -> Result<()> {
+	// The usual client times out whole requests, which a stream never
+	// finishes, so this one relies on keepalives instead.
+	let client = Client::builder()
+		.user_agent("inventor_bot")
+		.connect_timeout(Duration::from_secs(30))
+		.timeout(None)
+		.tcp_keepalive(Duration::from_secs(60))
+		.build()
+		.context("Failed to construct streaming client.")?;
+	let url = base(instance);
+	let token = token.to_owned();
+	// named after the account it's for, if there's more than one
+	let mut builder = thread::Builder::new();
+	if let Some(name) = thread::current().name() {
+		builder = builder.name(name.to_owned());
+	}
+	builder.spawn(move || {
+		let mut backoff = MIN_BACKOFF;
+		loop {
+			let result =
+				stream(&client, &url, &token, &send, &mut backoff);
+			if send.send(Event::Disconnected).is_err() {
+				return;
+			}
+			match result {
+				Ok(()) => return,
+				Err(e) => {
+					log::warn!("{e} Reconnecting in {backoff:?}.")
+				},
+			}
+			thread::sleep(backoff);
+			backoff = (backoff * 2).min(MAX_BACKOFF);
+		}
+	})?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::mpsc;
+
+	use super::*;
+	use crate::api::mock;
+
+	const BODY: &str = "\
+:thump
+
+event: notification
+data: {\"type\":\"mention\",\"account\":{\"acct\":\"alice\"},
+data: \"status\":{\"id\":\"1\"}}
+
+event: update
+data: {\"type\":\"mention\",\"account\":{\"acct\":\"nobody\"}}
+
+event: notification
+data: {\"type\":\"poll\",\"account\":{\"acct\":\"carol\"}}
+
+event: notification
+data: not json
+
+event: notification
+data: {\"type\":\"favourite\",\"account\":{\"acct\":\"bob\"},
+data: \"status\":{\"id\":\"2\"}}
+
+event: notification
+data: {\"type\":\"follow\",\"account\":{\"acct\":\"dave\"}}
+
+";
+
+	#[test]
+	fn events_from_stream() {
+		let (url, server) =
+			mock::serve("text/event-stream", vec![(200, BODY)]);
+		let (send, receive) = mpsc::channel();
+		let mut backoff = MAX_BACKOFF;
+		let result = stream(
+			&Client::new(),
+			&url,
+			"token",
+			&send,
+			&mut backoff,
+		);
+		assert!(result.is_err(), "the stream ending is an error");
+		assert_eq!(backoff, MIN_BACKOFF);
+
+		let seen = server.join().unwrap();
+		assert_eq!(
+			seen[0].line,
+			"GET /api/v1/streaming/user/notification HTTP/1.1"
+		);
+		assert_eq!(seen[0].header("Authorization"), Some("token"));
+		assert!(seen[0].body.is_empty());
+		assert_eq!(
+			seen[0].header("Accept"),
+			Some("text/event-stream")
+		);
+
+		let events = receive.try_iter().collect::<Vec<_>>();
+		assert_eq!(events.len(), 4, "{events:?}");
+		assert!(matches!(events[0], Event::Connected));
+		assert!(matches!(
+			&events[1],
+			Event::Mention { acct } if acct == "alice"
+		));
+		assert!(matches!(
+			&events[2],
+			Event::Favourite { acct, status }
+				if acct == "bob" && status == "2"
+		));
+		assert!(matches!(
+			&events[3],
+			Event::Follow { acct } if acct == "dave"
+		));
+	}
+}
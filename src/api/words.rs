@@ -1,22 +1,138 @@
-use anyhow::Result;
+use std::fs;
 
-// https://random-word-form.herokuapp.com/random/noun
-pub fn gen_item() -> Result<String> {
-	log::info!("Generating random invention");
-	let response = &super::RQ_CLIENT
-		.get()
-		.unwrap()
-		.get("https://random-word-form.herokuapp.com/random/noun")
-		.send()?
-		.text()?;
-	let [word] = serde_json::from_str::<[&str; 1]>(response)?;
+use anyhow::{anyhow, Context, Result};
+use rand::{seq::SliceRandom, Rng};
 
-	let mut str = String::from(match rand::random::<bool>() {
-		true => "the ",
-		false => "",
-	});
+use crate::data::{
+	config::{Config, Source},
+	english::Dictionary,
+	grammar::{self, Grammar},
+};
+
+const BUNDLED: &str = include_str!("../../assets/nouns.txt");
+
+pub enum WordSource {
+	Api,
+	List(Vec<String>),
+	Grammar(Grammar),
+}
 
-	str.push_str(word);
+impl WordSource {
+	fn bundled() -> Self {
+		Self::List(parse_list(BUNDLED))
+	}
+
+	fn file(config: &Config) -> Result<Self> {
+		let path = config
+			.words
+			.file
+			.as_ref()
+			.ok_or(anyhow!("No wordlist file was given."))?;
+		let file = fs::read_to_string(path).context(format!(
+			"Failed to read wordlist at {:?}.",
+			path
+		))?;
+		let list = parse_list(&file);
+		if list.is_empty() {
+			return Err(anyhow!("Wordlist at {:?} is empty.", path));
+		}
+		Ok(Self::List(list))
+	}
+
+	fn noun(&self, rng: &mut impl Rng) -> Result<String> {
+		match self {
+			Self::Api => api_noun(),
+			Self::List(list) => list
+				.choose(rng)
+				.cloned()
+				.ok_or(anyhow!("Wordlist is empty.")),
+			Self::Grammar(grammar) => {
+				Ok(grammar.expand(grammar::INVENTION, rng))
+			},
+		}
+	}
+}
+
+fn parse_list(file: &str) -> Vec<String> {
+	file.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.map(str::to_owned)
+		.collect()
+}
 
-	Ok(str)
+pub struct Words {
+	primary: (Source, WordSource),
+	fallback: Option<(Source, WordSource)>,
+}
+
+impl Words {
+	pub fn new(config: &Config) -> Result<Self> {
+		let words = &config.words;
+		let primary = (
+			words.source,
+			match words.source {
+				Source::Api => WordSource::Api,
+				Source::Bundled => WordSource::bundled(),
+				Source::File => WordSource::file(config)?,
+				Source::Grammar => WordSource::Grammar(
+					config
+						.grammar
+						.clone()
+						.ok_or(anyhow!("No grammar was given."))?,
+				),
+			},
+		);
+		let fallback = match (&words.source, words.fallback) {
+			(Source::Api, true) if words.file.is_some() => {
+				Some((Source::File, WordSource::file(config)?))
+			},
+			(Source::Api, true) => {
+				Some((Source::Bundled, WordSource::bundled()))
+			},
+			_ => None,
+		};
+		Ok(Self { primary, fallback })
+	}
+
+	/// Picks a noun, returning it along with the source it came from.
+	fn noun(&self, rng: &mut impl Rng) -> Result<(String, Source)> {
+		let (source, primary) = &self.primary;
+		match (primary.noun(rng), &self.fallback) {
+			(Ok(word), _) => Ok((word, *source)),
+			(Err(e), Some((source, fallback))) => {
+				log::warn!(
+					"{}",
+					e.context(
+						"Failed to fetch a word, using fallback."
+					)
+				);
+				Ok((fallback.noun(rng)?, *source))
+			},
+			(Err(e), None) => Err(e),
+		}
+	}
+}
+
+// https://random-word-form.herokuapp.com/random/noun
+fn api_noun() -> Result<String> {
+	// there's usually a fallback, which is better than waiting
+	let response = &super::request::send_once(|client| {
+		client
+			.get("https://random-word-form.herokuapp.com/random/noun")
+	})?
+	.error_for_status()?
+	.text()?;
+	let [word] = serde_json::from_str::<[&str; 1]>(response)?;
+	Ok(word.to_owned())
+}
+
+pub fn gen_item(
+	words: &Words,
+	dictionary: &Dictionary,
+	rng: &mut impl Rng,
+) -> Result<(String, Source)> {
+	log::info!("Generating random invention");
+	let (word, source) = words.noun(rng)?;
+	Ok((dictionary.inflect(&word, rng), source))
 }
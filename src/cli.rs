@@ -1,13 +1,15 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 use anyhow::{Context, Result};
 use log::{Level, LevelFilter};
 use simplelog::{
 	Color, ColorChoice::Auto, ConfigBuilder, LevelPadding,
-	TermLogger, TerminalMode,
+	TermLogger, TerminalMode, ThreadLogMode, ThreadPadding,
 };
 
+use crate::data::config::Seed;
+
 macro_rules! env_prefix {
 	($lit:literal) => {
 		concat!("INVENTOR_BOT_", $lit)
@@ -55,10 +57,23 @@ pub struct Command {
 		help = "The authorisation token for the bot to use.",
 		long_help = "The authorisation token for the bot to use. This will override \
 			whatever is in the cache unless `--no-cache` is also passed. The token \
-			must have `write:statuses permissions.",
+			must have `read:statuses`, `write:statuses` and `write:media` \
+			permissions, and \
+			`read:notifications` permissions if mentions are enabled, and \
+			`write:accounts` permissions if the profile is set.",
 	)]
 	pub token: Option<String>,
 
+	#[arg(
+		short, long,
+		env = env_prefix!("ACCOUNT"),
+		value_name = "NAME",
+		help = "Only use the account with this name.",
+		long_help = "Only use the account with this name, out of the \
+			`[[accounts]]` in the config file. By default, every account is used.",
+	)]
+	pub account: Option<String>,
+
 	#[arg(
 		short, long,
 		env = env_prefix!("NO_CACHE"),
@@ -75,6 +90,109 @@ pub struct Command {
 		help = "Don't actually try and post a status, just print the resulting message.",
 	)]
 	pub dry_run: bool,
+
+	#[arg(
+		short, long,
+		env = env_prefix!("SEED"),
+		value_name = "SEED",
+		help = "Seed the random number generator, either with an integer or 'daily'.",
+		long_help = "Seed the random number generator, so that the same posts are \
+			generated every time. Either an integer, or 'daily' to derive the seed \
+			from the current date. Posts will only be reproducible if the words \
+			come from a local source.",
+	)]
+	pub seed: Option<Seed>,
+
+	#[command(subcommand)]
+	pub action: Option<Action>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Action {
+	#[command(about = "List or search previously made posts.")]
+	History(HistoryArgs),
+	#[command(
+		subcommand,
+		about = "Schedule posts ahead of time on the instance."
+	)]
+	Schedule(ScheduleAction),
+	#[command(about = "Delete old posts, following `[retention]`.")]
+	Prune(PruneArgs),
+	#[command(
+		about = "Show which inventors and word sources do best."
+	)]
+	Stats,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ScheduleAction {
+	#[command(about = "Schedule the next COUNT posts.")]
+	Add(ScheduleArgs),
+	#[command(about = "List the posts that are scheduled.")]
+	List,
+	#[command(about = "Cancel scheduled posts.")]
+	Cancel(CancelArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ScheduleArgs {
+	#[arg(help = "How many posts to schedule.")]
+	pub count: usize,
+
+	#[arg(
+		short,
+		long,
+		value_name = "MINUTES",
+		help = "The time between scheduled posts.",
+		long_help = "The time between scheduled posts, in minutes. \
+			Defaults to `repeat` from the config."
+	)]
+	pub every: Option<u64>,
+}
+
+#[derive(Debug, Args)]
+pub struct CancelArgs {
+	#[arg(
+		help = "The ids of the scheduled posts to cancel.",
+		required_unless_present = "all"
+	)]
+	pub ids: Vec<String>,
+
+	#[arg(
+		short,
+		long,
+		help = "Cancel every scheduled post.",
+		conflicts_with = "ids"
+	)]
+	pub all: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct PruneArgs {
+	#[arg(
+		short,
+		long,
+		help = "Only list the posts that would be deleted."
+	)]
+	pub list: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct HistoryArgs {
+	#[arg(
+		help = "Only show posts containing this text.",
+		long_help = "Only show posts whose inventor, invention, or text \
+			contains this, ignoring case."
+	)]
+	pub query: Option<String>,
+
+	#[arg(
+		short = 'n',
+		long,
+		value_name = "COUNT",
+		help = "Only show the most recent COUNT posts."
+	)]
+	pub limit: Option<usize>,
 }
 
 pub fn init() -> Result<Command> {
@@ -87,6 +205,10 @@ pub fn init() -> Result<Command> {
 			.set_level_color(Level::Info, Some(Color::Cyan))
 			.set_level_color(Level::Debug, Some(Color::Magenta))
 			.set_level_padding(LevelPadding::Right)
+			// the names of accounts, when there's more than one
+			.set_thread_level(LevelFilter::Error)
+			.set_thread_mode(ThreadLogMode::Names)
+			.set_thread_padding(ThreadPadding::Off)
 			.build(),
 		TerminalMode::Mixed,
 		Auto,
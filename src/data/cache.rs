@@ -13,8 +13,8 @@ pub struct Cache {
 impl Cache {
 	fn get(path: impl AsRef<Path>) -> Result<Self> {
 		let path = path.as_ref();
-		super::is_valid(&path, true)?;
-		let file = fs::read_to_string(&path)?;
+		super::is_valid(path, true)?;
+		let file = fs::read_to_string(path)?;
 		Ok(toml::de::from_str::<Self>(&file)?)
 	}
 
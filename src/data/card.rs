@@ -0,0 +1,318 @@
+use ab_glyph::{Font, FontRef, PxScale, ScaleFont};
+use anyhow::{Context, Result};
+
+use super::template::{Template, Values};
+
+const REGULAR: &[u8] =
+	include_bytes!("../../assets/fonts/DejaVuSerif.ttf");
+const BOLD: &[u8] =
+	include_bytes!("../../assets/fonts/DejaVuSerif-Bold.ttf");
+
+/// The alt text used when `card.alt` isn't set.
+pub const ALT: &str = "A patent certificate, number {count} and \
+	dated {date}, certifying that {inventor} invented {invention}.";
+
+pub const WIDTH: u32 = 1200;
+pub const HEIGHT: u32 = 675;
+const MARGIN: f32 = 120.0;
+
+type Colour = [u8; 3];
+const PAPER: Colour = [0xf4, 0xec, 0xd6];
+const INK: Colour = [0x3b, 0x2a, 0x1a];
+const FADED: Colour = [0x7a, 0x62, 0x48];
+const GOLD: Colour = [0xc0, 0x93, 0x2f];
+
+/// A "patent certificate" for a single post.
+pub struct Card<'a> {
+	pub values: Values<'a>,
+}
+
+impl Card<'_> {
+	pub fn alt(&self, template: Option<&Template>) -> String {
+		match template {
+			Some(template) => template.render(&self.values),
+			None => Template::new(ALT.to_owned(), 1)
+				.expect("The default alt text is valid.")
+				.render(&self.values),
+		}
+	}
+
+	/// Draws the card, returning it encoded as a PNG.
+	pub fn render(&self) -> Result<Vec<u8>> {
+		let regular = FontRef::try_from_slice(REGULAR)?;
+		let bold = FontRef::try_from_slice(BOLD)?;
+		let values = &self.values;
+		let mut canvas = Canvas::new(WIDTH, HEIGHT, PAPER);
+
+		// borders
+		canvas.frame(24.0, 6.0, INK);
+		canvas.frame(40.0, 2.0, FADED);
+
+		let centre = WIDTH as f32 / 2.0;
+		let max = WIDTH as f32 - MARGIN * 2.0;
+		canvas.text(
+			&bold,
+			60.0,
+			centre,
+			140.0,
+			"LETTERS PATENT",
+			INK,
+		);
+		canvas.rect(centre - 160.0, 168.0, 320.0, 2.0, GOLD);
+		canvas.text(
+			&regular,
+			30.0,
+			centre,
+			230.0,
+			"Be it known that",
+			FADED,
+		);
+		let mut y = canvas.fitted(&bold, values.inventor, 290.0, max);
+		y += 10.0;
+		canvas.text(
+			&regular,
+			30.0,
+			centre,
+			y,
+			"is hereby recognised as the inventor of",
+			FADED,
+		);
+		canvas.fitted(&bold, values.invention, y + 60.0, max);
+
+		// footer
+		let footer = HEIGHT as f32 - 80.0;
+		let number = format!("Patent No. {}", values.count);
+		let date = format!("Granted {}", values.date);
+		let left = WIDTH as f32 / 4.0;
+		canvas.text(&regular, 26.0, left, footer, &number, INK);
+		canvas.text(&regular, 26.0, centre, footer, &date, INK);
+
+		// seal
+		let (x, y) = (WIDTH as f32 - MARGIN - 40.0, footer - 20.0);
+		canvas.disc(x, y, 56.0, GOLD);
+		canvas.disc(x, y, 44.0, PAPER);
+		canvas.disc(x, y, 38.0, GOLD);
+
+		canvas.encode()
+	}
+}
+
+struct Canvas {
+	width: u32,
+	height: u32,
+	pixels: Vec<Colour>,
+}
+
+impl Canvas {
+	fn new(width: u32, height: u32, background: Colour) -> Self {
+		Self {
+			width,
+			height,
+			pixels: vec![background; (width * height) as usize],
+		}
+	}
+
+	/// Mixes `colour` into the pixel at `(x, y)` by `alpha`.
+	fn blend(&mut self, x: i64, y: i64, colour: Colour, alpha: f32) {
+		if x < 0
+			|| y < 0 || x >= self.width as i64
+			|| y >= self.height as i64
+		{
+			return;
+		}
+		let alpha = alpha.clamp(0.0, 1.0);
+		let pixel =
+			&mut self.pixels[(y * self.width as i64 + x) as usize];
+		for (p, c) in pixel.iter_mut().zip(colour) {
+			*p = (*p as f32 * (1.0 - alpha) + c as f32 * alpha)
+				.round() as u8;
+		}
+	}
+
+	fn rect(
+		&mut self,
+		x: f32,
+		y: f32,
+		w: f32,
+		h: f32,
+		colour: Colour,
+	) {
+		for py in y as i64..(y + h) as i64 {
+			for px in x as i64..(x + w) as i64 {
+				self.blend(px, py, colour, 1.0);
+			}
+		}
+	}
+
+	/// An outline `inset` pixels in from the edges.
+	fn frame(&mut self, inset: f32, thickness: f32, colour: Colour) {
+		let (w, h) = (self.width as f32, self.height as f32);
+		let inner = (w - inset * 2.0, h - inset * 2.0);
+		self.rect(inset, inset, inner.0, thickness, colour);
+		self.rect(
+			inset,
+			h - inset - thickness,
+			inner.0,
+			thickness,
+			colour,
+		);
+		self.rect(inset, inset, thickness, inner.1, colour);
+		self.rect(
+			w - inset - thickness,
+			inset,
+			thickness,
+			inner.1,
+			colour,
+		);
+	}
+
+	fn disc(&mut self, x: f32, y: f32, radius: f32, colour: Colour) {
+		let r = radius.ceil() as i64 + 1;
+		for py in y as i64 - r..=y as i64 + r {
+			for px in x as i64 - r..=x as i64 + r {
+				let d = ((px as f32 + 0.5 - x).powi(2)
+					+ (py as f32 + 0.5 - y).powi(2))
+				.sqrt();
+				// a one pixel ramp at the edge for anti-aliasing
+				self.blend(px, py, colour, radius - d + 0.5);
+			}
+		}
+	}
+
+	fn width_of(font: &FontRef, size: f32, text: &str) -> f32 {
+		let font = font.as_scaled(PxScale::from(size));
+		let mut width = 0.0;
+		let mut last = None;
+		for c in text.chars() {
+			let id = font.glyph_id(c);
+			if let Some(last) = last {
+				width += font.kern(last, id);
+			}
+			width += font.h_advance(id);
+			last = Some(id);
+		}
+		width
+	}
+
+	/// Draws a line of text centred on `x`, with its baseline at `y`.
+	fn text(
+		&mut self,
+		font: &FontRef,
+		size: f32,
+		x: f32,
+		y: f32,
+		text: &str,
+		colour: Colour,
+	) {
+		let scaled = font.as_scaled(PxScale::from(size));
+		let mut caret = x - Self::width_of(font, size, text) / 2.0;
+		let mut last = None;
+		for c in text.chars() {
+			let id = scaled.glyph_id(c);
+			if let Some(last) = last {
+				caret += scaled.kern(last, id);
+			}
+			let glyph = id.with_scale_and_position(
+				PxScale::from(size),
+				ab_glyph::point(caret, y),
+			);
+			caret += scaled.h_advance(id);
+			last = Some(id);
+			let Some(outline) = font.outline_glyph(glyph) else {
+				continue;
+			};
+			let bounds = outline.px_bounds();
+			outline.draw(|gx, gy, coverage| {
+				self.blend(
+					bounds.min.x as i64 + gx as i64,
+					bounds.min.y as i64 + gy as i64,
+					colour,
+					coverage,
+				);
+			});
+		}
+	}
+
+	/// Draws `text` as large as will fit within `max` pixels, wrapping
+	/// onto a second line if it has to. Returns the baseline of the
+	/// line after it.
+	fn fitted(
+		&mut self,
+		font: &FontRef,
+		text: &str,
+		y: f32,
+		max: f32,
+	) -> f32 {
+		let mut size = 56.0;
+		let lines = loop {
+			let lines = wrap(font, size, text, max);
+			if lines.len() <= 2 || size <= 24.0 {
+				break lines;
+			}
+			size -= 4.0;
+		};
+		let mut y = y;
+		for line in lines {
+			let mut line_size = size;
+			while Self::width_of(font, line_size, &line) > max
+				&& line_size > 12.0
+			{
+				line_size -= 2.0;
+			}
+			self.text(
+				font,
+				line_size,
+				WIDTH as f32 / 2.0,
+				y,
+				&line,
+				INK,
+			);
+			y += size * 1.2;
+		}
+		y
+	}
+
+	fn encode(&self) -> Result<Vec<u8>> {
+		let mut out = Vec::new();
+		let mut encoder =
+			png::Encoder::new(&mut out, self.width, self.height);
+		encoder.set_color(png::ColorType::Rgb);
+		encoder.set_depth(png::BitDepth::Eight);
+		let mut writer = encoder
+			.write_header()
+			.context("Failed to encode card.")?;
+		writer
+			.write_image_data(self.pixels.as_flattened())
+			.context("Failed to encode card.")?;
+		writer.finish()?;
+		Ok(out)
+	}
+}
+
+/// Splits `text` into lines no wider than `max`, breaking between words.
+fn wrap(
+	font: &FontRef,
+	size: f32,
+	text: &str,
+	max: f32,
+) -> Vec<String> {
+	let mut lines = Vec::new();
+	let mut line = String::new();
+	for word in text.split_whitespace() {
+		let candidate = match line.is_empty() {
+			true => word.to_owned(),
+			false => format!("{line} {word}"),
+		};
+		if !line.is_empty()
+			&& Canvas::width_of(font, size, &candidate) > max
+		{
+			lines.push(std::mem::replace(&mut line, word.to_owned()));
+		} else {
+			line = candidate;
+		}
+	}
+	if !line.is_empty() {
+		lines.push(line);
+	}
+	lines
+}
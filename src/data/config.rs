@@ -1,23 +1,88 @@
-use std::{fs, path::PathBuf};
+use std::{collections::BTreeMap, fs, path::PathBuf, str::FromStr};
 
-use anyhow::{Context, Result};
-use serde::Deserialize;
+use anyhow::{anyhow, Context, Result};
+use rand::distributions::WeightedIndex;
+use serde::{Deserialize, Serialize};
 use toml;
 
-use crate::cli::Command;
+use super::{
+	filter::{self, Blocklist},
+	grammar::{self, Grammar, Rule},
+	profile,
+	template::{self, Slot, Template},
+};
+use crate::cli::{Action, Command};
 
 fn default_lang() -> String { String::from("en") }
 fn default_client_name() -> String { String::from("inventor_bot") }
 const fn default_port() -> u16 { 65233 }
+const fn default_true() -> bool { true }
+const fn default_plural() -> f64 { 0.25 }
+const fn default_definite() -> f64 { 0.5 }
+const fn default_attempts() -> usize { 10 }
+const fn default_interval() -> u64 { 1 }
+const fn default_limit() -> usize { 3 }
+const fn default_window() -> u64 { 60 }
+const fn default_chance() -> f64 { 1.0 }
+const fn default_poll_hours() -> u64 { 24 }
+const fn default_stats_days() -> u64 { 7 }
+const fn default_stats_hours() -> u64 { 6 }
+const fn default_floor() -> f64 { 0.25 }
+fn default_poll_options() -> Vec<String> {
+	vec![String::from("Genius"), String::from("Menace")]
+}
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
+	/// The name of the account, when there's more than one.
+	#[serde(skip)]
+	pub name: Option<String>,
 	pub instance: String,
 	#[serde(default)]
-	pub inventors: Vec<String>,
+	pub backend: Backend,
+	pub token: Option<String>,
+	#[serde(default)]
+	pub bluesky: Bluesky,
+	/// The names of accounts to post a copy of each post to.
+	#[serde(default)]
+	pub crosspost: Vec<String>,
+	#[serde(default)]
+	pub inventors: Vec<Inventor>,
 	pub repeat: Option<u64>,
+	pub seed: Option<Seed>,
 	#[serde(default)]
-	pub visibility: Visibility,
+	pub visibility: VisibilityMode,
+	pub spoiler: Option<Spoiler>,
+	#[serde(default)]
+	pub sensitive: bool,
+	pub language: Option<String>,
+	#[serde(default)]
+	pub words: Words,
+	#[serde(rename = "grammar")]
+	pub grammar_file: Option<PathBuf>,
+	#[serde(skip)]
+	pub grammar: Option<Grammar>,
+	#[serde(default)]
+	pub english: English,
+	#[serde(default)]
+	pub templates: Templates,
+	#[serde(default)]
+	pub history: History,
+	#[serde(default)]
+	pub filter: Filter,
+	#[serde(default = "default_attempts")]
+	pub attempts: usize,
+	#[serde(default)]
+	pub card: Card,
+	#[serde(default)]
+	pub mentions: Mentions,
+	#[serde(default)]
+	pub poll: Poll,
+	#[serde(default)]
+	pub retention: Retention,
+	#[serde(default)]
+	pub stats: Stats,
+	pub profile: Option<Profile>,
 
 	pub cache: Cache,
 
@@ -29,22 +94,461 @@ pub struct Config {
 	pub lang: String,
 	#[serde(default)]
 	pub dry_run: bool,
+	/// Whether the bot was run to manage scheduled posts.
+	#[serde(skip)]
+	pub scheduling: bool,
+}
+
+/// Which API the instance speaks.
+#[derive(
+	Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+	/// Also Pleroma, Akkoma, GoToSocial, and anything else with a
+	/// Mastodon compatible API.
+	#[default]
+	Mastodon,
+	#[serde(
+		alias = "firefish",
+		alias = "sharkey",
+		alias = "iceshrimp"
+	)]
+	Misskey,
+	Bluesky,
 }
 
 #[derive(Debug, Deserialize, Default)]
+pub struct Bluesky {
+	/// The handle or email address to log in with.
+	pub handle: Option<String>,
+	/// An app password, asked for when logging in if it isn't given.
+	pub password: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawInventor {
+	Plain(String),
+	Weighted { name: String, weight: u32 },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(from = "RawInventor")]
+pub struct Inventor {
+	pub name: String,
+	pub weight: u32,
+	pub rule: Option<Rule>,
+}
+
+impl From<RawInventor> for Inventor {
+	fn from(raw: RawInventor) -> Self {
+		let (name, weight) = match raw {
+			RawInventor::Plain(name) => (name, 1),
+			RawInventor::Weighted { name, weight } => (name, weight),
+		};
+		Self {
+			name,
+			weight,
+			rule: None,
+		}
+	}
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawSeed {
+	Number(u64),
+	Text(String),
+}
+
+/// What to seed the random number generator with. A daily seed is
+/// derived from the current date (in UTC), so that every bot using it
+/// agrees on the first post of each day.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "RawSeed")]
+pub enum Seed {
+	Fixed(u64),
+	Daily,
+}
+
+impl FromStr for Seed {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"daily" => Ok(Self::Daily),
+			s => s.parse().map(Self::Fixed).map_err(|_| {
+				format!(
+					"Expected an integer or \"daily\", found {s:?}."
+				)
+			}),
+		}
+	}
+}
+
+impl TryFrom<RawSeed> for Seed {
+	type Error = String;
+
+	fn try_from(raw: RawSeed) -> Result<Self, Self::Error> {
+		match raw {
+			RawSeed::Number(n) => Ok(Self::Fixed(n)),
+			RawSeed::Text(s) => s.parse(),
+		}
+	}
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawSpoiler {
+	Always(String),
+	Chance { text: String, chance: f64 },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(from = "RawSpoiler")]
+pub struct Spoiler {
+	pub text: String,
+	pub chance: f64,
+}
+
+impl From<RawSpoiler> for Spoiler {
+	fn from(raw: RawSpoiler) -> Self {
+		match raw {
+			RawSpoiler::Always(text) => Self { text, chance: 1.0 },
+			RawSpoiler::Chance { text, chance } => {
+				Self { text, chance }
+			},
+		}
+	}
+}
+
+#[derive(
+	Debug,
+	Clone,
+	Copy,
+	Serialize,
+	Deserialize,
+	Default,
+	PartialEq,
+	Eq,
+	PartialOrd,
+	Ord,
+)]
+#[serde(rename_all = "lowercase")]
 pub enum Visibility {
+	#[serde(alias = "Public")]
 	Public,
 	#[default]
+	#[serde(alias = "Unlisted")]
 	Unlisted,
+	#[serde(alias = "Private")]
+	Private,
+	#[serde(alias = "Direct")]
+	Direct,
+}
+
+impl From<&Visibility> for &str {
+	fn from(visibility: &Visibility) -> &'static str {
+		match visibility {
+			Visibility::Public => "public",
+			Visibility::Unlisted => "unlisted",
+			Visibility::Private => "private",
+			Visibility::Direct => "direct",
+		}
+	}
+}
+
+/// Use `visibility` for a post if there hasn't been a post with that
+/// visibility for at least `hours`.
+#[derive(Debug, Deserialize)]
+pub struct Every {
+	pub visibility: Visibility,
+	pub hours: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(try_from = "toml::Value")]
+pub struct VisibilityMode {
+	pub default: Visibility,
+	pub weights: BTreeMap<Visibility, u32>,
+	pub every: Vec<Every>,
+}
+
+impl VisibilityMode {
+	/// Every visibility a post might be given.
+	pub fn all(&self) -> impl Iterator<Item = Visibility> + '_ {
+		let default = self.weights.is_empty().then_some(self.default);
+		let weighted = self
+			.weights
+			.iter()
+			.filter(|(_, weight)| **weight > 0)
+			.map(|(visibility, _)| *visibility);
+		default
+			.into_iter()
+			.chain(weighted)
+			.chain(self.every.iter().map(|every| every.visibility))
+	}
+}
+
+impl TryFrom<toml::Value> for VisibilityMode {
+	type Error = toml::de::Error;
+
+	// Either a single visibility, or a table. Going through a value
+	// rather than an untagged enum keeps the error messages useful.
+	fn try_from(value: toml::Value) -> Result<Self, Self::Error> {
+		#[derive(Deserialize)]
+		struct Mixed {
+			#[serde(default)]
+			default: Visibility,
+			#[serde(default)]
+			weights: BTreeMap<Visibility, u32>,
+			#[serde(default)]
+			every: Vec<Every>,
+		}
+		match value {
+			toml::Value::Table(_) => {
+				let mixed = value.try_into::<Mixed>()?;
+				Ok(Self {
+					default: mixed.default,
+					weights: mixed.weights,
+					every: mixed.every,
+				})
+			},
+			value => Ok(Self {
+				default: value.try_into()?,
+				..Default::default()
+			}),
+		}
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Words {
+	#[serde(default)]
+	pub source: Source,
+	pub file: Option<PathBuf>,
+	#[serde(default = "default_true")]
+	pub fallback: bool,
 }
 
-impl Into<&str> for &Visibility {
-    fn into(self) -> &'static str {
-		match self {
-			&Visibility::Public => "public",
-			&Visibility::Unlisted => "unlisted",
+impl Default for Words {
+	fn default() -> Self {
+		Self {
+			source: Source::default(),
+			file: None,
+			fallback: true,
 		}
-    }
+	}
+}
+
+#[derive(
+	Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+	#[default]
+	Api,
+	Bundled,
+	File,
+	Grammar,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct English {
+	#[serde(default = "default_plural")]
+	pub plural: f64,
+	#[serde(default = "default_definite")]
+	pub definite: f64,
+	pub dictionary: Option<PathBuf>,
+}
+
+impl Default for English {
+	fn default() -> Self {
+		Self {
+			plural: default_plural(),
+			definite: default_definite(),
+			dictionary: None,
+		}
+	}
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct Templates {
+	#[serde(default)]
+	pub status: Vec<Template>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct History {
+	#[serde(default = "default_true")]
+	pub enable: bool,
+	pub avoid_posts: Option<usize>,
+	pub avoid_days: Option<u64>,
+}
+
+impl Default for History {
+	fn default() -> Self {
+		Self {
+			enable: true,
+			avoid_posts: None,
+			avoid_days: None,
+		}
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Filter {
+	#[serde(default = "default_true")]
+	pub default: bool,
+	#[serde(flatten)]
+	pub blocklist: Blocklist,
+}
+
+impl Default for Filter {
+	fn default() -> Self {
+		Self {
+			default: true,
+			blocklist: Blocklist::default(),
+		}
+	}
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct Card {
+	#[serde(default)]
+	pub enable: bool,
+	pub alt: Option<Template>,
+	pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Mentions {
+	#[serde(default)]
+	pub enable: bool,
+	#[serde(default = "default_true")]
+	pub streaming: bool,
+	#[serde(default = "default_interval")]
+	pub interval: u64,
+	#[serde(default)]
+	pub visibility: Visibility,
+	#[serde(default = "default_limit")]
+	pub limit: usize,
+	#[serde(default = "default_window")]
+	pub window: u64,
+	#[serde(default)]
+	pub allow: Vec<String>,
+	#[serde(default)]
+	pub deny: Vec<String>,
+	#[serde(default)]
+	pub bots: bool,
+}
+
+impl Default for Mentions {
+	fn default() -> Self {
+		Self {
+			enable: false,
+			streaming: true,
+			interval: default_interval(),
+			visibility: Visibility::default(),
+			limit: default_limit(),
+			window: default_window(),
+			allow: Vec::new(),
+			deny: Vec::new(),
+			bots: false,
+		}
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Poll {
+	#[serde(default)]
+	pub enable: bool,
+	#[serde(default = "default_chance")]
+	pub chance: f64,
+	#[serde(default = "default_poll_options")]
+	pub options: Vec<String>,
+	#[serde(default = "default_poll_hours")]
+	pub hours: u64,
+}
+
+impl Default for Poll {
+	fn default() -> Self {
+		Self {
+			enable: false,
+			chance: default_chance(),
+			options: default_poll_options(),
+			hours: default_poll_hours(),
+		}
+	}
+}
+
+/// When the bot's own posts are deleted. A post is deleted once it's
+/// older than `days`, or isn't one of the newest `keep`, unless it
+/// has more than `favourites` favourites.
+#[derive(Debug, Deserialize, Default)]
+pub struct Retention {
+	pub days: Option<u64>,
+	pub keep: Option<usize>,
+	pub favourites: Option<u64>,
+}
+
+impl Retention {
+	pub fn enabled(&self) -> bool {
+		self.days.is_some() || self.keep.is_some()
+	}
+}
+
+/// Keeping track of how well posts do. Posts are checked every
+/// `hours` until they're `days` old. With `adaptive`, inventors are
+/// picked more often the better their posts do, but never less than
+/// `floor` times as often as their weight says.
+#[derive(Debug, Deserialize)]
+pub struct Stats {
+	#[serde(default)]
+	pub enable: bool,
+	#[serde(default = "default_stats_days")]
+	pub days: u64,
+	#[serde(default = "default_stats_hours")]
+	pub hours: u64,
+	#[serde(default)]
+	pub adaptive: bool,
+	#[serde(default = "default_floor")]
+	pub floor: f64,
+}
+
+impl Default for Stats {
+	fn default() -> Self {
+		Self {
+			enable: false,
+			days: default_stats_days(),
+			hours: default_stats_hours(),
+			adaptive: false,
+			floor: default_floor(),
+		}
+	}
+}
+
+/// How the bot's account should look. Anything left out is left as it
+/// is on the instance.
+#[derive(Debug, Deserialize)]
+pub struct Profile {
+	pub display_name: Option<String>,
+	/// Can use the same slots as templates, filled in from the latest
+	/// post.
+	pub bio: Option<Template>,
+	pub fields: Option<Vec<Field>>,
+	pub bot: Option<bool>,
+	pub discoverable: Option<bool>,
+	pub avatar: Option<PathBuf>,
+	pub header: Option<PathBuf>,
+}
+
+/// A name and value shown in a table on the bot's profile.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Field {
+	pub name: String,
+	pub value: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -57,8 +561,35 @@ pub struct Cache {
 	pub protect: bool,
 }
 
+/// Merges `from` into `into`, with tables merged key by key and
+/// anything else in `from` replacing what's in `into`.
+fn merge(into: &mut toml::Table, from: toml::Table) {
+	for (key, value) in from {
+		match (into.get_mut(&key), value) {
+			(
+				Some(toml::Value::Table(into)),
+				toml::Value::Table(from),
+			) => merge(into, from),
+			(_, value) => {
+				into.insert(key, value);
+			},
+		}
+	}
+}
+
+/// Account names are used as directory names in the cache.
+fn is_valid_name(name: &str) -> bool {
+	!name.is_empty()
+		&& !name.starts_with('.')
+		&& name
+			.chars()
+			.all(|c| c.is_alphanumeric() || "-_.@".contains(c))
+}
+
 impl Config {
-	pub fn get(command: &Command) -> Result<Self> {
+	/// Reads the config file, returning a config for each account in
+	/// it, or just the one if there are no `[[accounts]]`.
+	pub fn get(command: &Command) -> Result<Vec<Self>> {
 		let path = &command.config;
 		super::is_valid(path, false).context(format!(
 			"Config file at {:?} is not valid. \
@@ -70,14 +601,467 @@ impl Config {
 			"Failed to read config file at {:?}.",
 			path
 		))?;
-		let mut config = toml::de::from_str::<Self>(&file)
+		let mut base = toml::de::from_str::<toml::Table>(&file)
 			.context("Failed to parse config file.")?;
 
+		let accounts = match base.remove("accounts") {
+			None => {
+				if command.account.is_some() {
+					return Err(anyhow!(
+						"`--account` was given, but the config file has \
+						no `[[accounts]]`."
+					));
+				}
+				let config = toml::de::from_str::<Self>(&file)
+					.context("Failed to parse config file.")?;
+				if !config.crosspost.is_empty() {
+					return Err(anyhow!(
+						"`crosspost` names other accounts, so it can \
+						only be used in `[[accounts]]`."
+					));
+				}
+				return Ok(vec![config.check(command)?]);
+			},
+			Some(toml::Value::Array(accounts))
+				if !accounts.is_empty() =>
+			{
+				accounts
+			},
+			Some(_) => {
+				return Err(anyhow!(
+					"`accounts` must be a list of tables, each starting \
+					with `[[accounts]]`."
+				))
+			},
+		};
+
+		let mut names = Vec::new();
+		let mut configs = Vec::new();
+		for account in accounts {
+			let toml::Value::Table(account) = account else {
+				return Err(anyhow!(
+					"Each of `accounts` must be a table."
+				));
+			};
+			let Some(toml::Value::String(name)) = account.get("name")
+			else {
+				return Err(anyhow!(
+					"Each account must have a `name`."
+				));
+			};
+			let name = name.clone();
+			if !is_valid_name(&name) {
+				return Err(anyhow!(
+					"Account name {name:?} can only contain letters, \
+					numbers, and `-_.@`."
+				));
+			}
+			if names.contains(&name) {
+				return Err(anyhow!(
+					"There is more than one account named {name:?}."
+				));
+			}
+			names.push(name.clone());
+
+			let mut table = base.clone();
+			merge(&mut table, account);
+			let mut config = toml::Value::Table(table)
+				.try_into::<Self>()
+				.context(format!(
+					"Failed to parse account {name:?}."
+				))?;
+			config.name = Some(name.clone());
+			let config = config
+				.check(command)
+				.context(format!("Account {name:?} is not valid."))?;
+			configs.push(config);
+		}
+
+		let mut targets = Vec::new();
+		for config in &configs {
+			let name = config.name.as_deref().unwrap_or_default();
+			for target in &config.crosspost {
+				if !names.contains(target) || target == name {
+					return Err(anyhow!(
+						"Account {name:?} cross-posts to {target:?}, \
+						which isn't another account."
+					));
+				}
+				if targets.contains(target) {
+					return Err(anyhow!(
+						"More than one account cross-posts to \
+						{target:?}."
+					));
+				}
+				targets.push(target.clone());
+				// Bluesky posts can't be hidden or put behind a
+				// warning, so those posts would be skipped
+				let bluesky = configs.iter().any(|config| {
+					config.name.as_ref() == Some(target)
+						&& config.backend == Backend::Bluesky
+				});
+				let hidden = config
+					.visibility
+					.all()
+					.any(|v| v > Visibility::Unlisted);
+				let spoiler = config
+					.spoiler
+					.as_ref()
+					.is_some_and(|s| s.chance > 0.0);
+				if bluesky && (hidden || spoiler) {
+					return Err(anyhow!(
+						"{target:?} is on Bluesky, where every post \
+						is public and has no content warning, so \
+						{name:?} can't cross-post to it with private \
+						or direct visibility or a `spoiler`."
+					));
+				}
+			}
+		}
+		let chained = configs.iter().find(|config| {
+			let name = config.name.as_ref();
+			!config.crosspost.is_empty()
+				&& name.is_some_and(|n| targets.contains(n))
+		});
+		if let Some(config) = chained {
+			return Err(anyhow!(
+				"Account {:?} is cross-posted to, so it can't \
+				cross-post to other accounts itself.",
+				config.name.as_deref().unwrap_or_default()
+			));
+		}
+
+		// the account that was asked for, and any it cross-posts to
+		if let Some(account) = &command.account {
+			let Some(config) = configs
+				.iter()
+				.find(|config| config.name.as_ref() == Some(account))
+			else {
+				return Err(anyhow!(
+					"There is no account named {account:?}."
+				));
+			};
+			if targets.contains(account) {
+				return Err(anyhow!(
+					"Account {account:?} only posts what's \
+					cross-posted to it, pick the account that \
+					cross-posts to it instead."
+				));
+			}
+			let keep = config.crosspost.clone();
+			configs.retain(|config| {
+				config.name.as_ref().is_some_and(|name| {
+					name == account || keep.contains(name)
+				})
+			});
+		}
+		if command.token.is_some() && configs.len() > 1 {
+			return Err(anyhow!(
+				"`--token` can only be used with a single account, \
+				pick one with `--account`."
+			));
+		}
+		Ok(configs)
+	}
+
+	/// Applies command line overrides, and checks that everything in
+	/// the config makes sense.
+	fn check(self, command: &Command) -> Result<Self> {
+		let mut config = self;
 		if let Some(cache_dir) = &command.cache {
 			config.cache.path = cache_dir.to_path_buf();
 		};
+		// each account gets its own credentials, history and so on
+		if let Some(name) = &config.name {
+			config.cache.path = config.cache.path.join(name);
+		}
 		config.cache.enable = config.cache.enable && !command.no_cache;
 		config.dry_run = config.dry_run || command.dry_run;
+		config.scheduling =
+			matches!(command.action, Some(Action::Schedule(_)));
+		if let Some(seed) = command.seed {
+			config.seed = Some(seed);
+		}
+		if config.language.is_none() {
+			config.language = Some(config.lang.clone());
+		}
+
+		if config.words.source == Source::File
+			&& config.words.file.is_none()
+		{
+			return Err(anyhow!(
+				"`words.source` is set to \"file\", \
+				but no `words.file` was given."
+			));
+		}
+
+		if let Some(path) = &config.grammar_file {
+			let grammar = Grammar::load(path).context(format!(
+				"Grammar at {:?} is not valid.",
+				path
+			))?;
+			for inventor in &mut config.inventors {
+				let rule = Rule::parse(&inventor.name)?;
+				if let Some(name) =
+					rule.symbols().find(|name| !grammar.has(name))
+				{
+					return Err(anyhow!(
+						"Inventor {:?} uses the symbol `{name}`, \
+						but it is not defined in the grammar.",
+						inventor.name
+					));
+				}
+				if rule.has_symbols() {
+					inventor.rule = Some(rule);
+				}
+			}
+			config.grammar = Some(grammar);
+		}
+		if config.words.source == Source::Grammar
+			&& !config
+				.grammar
+				.as_ref()
+				.is_some_and(|g| g.has(grammar::INVENTION))
+		{
+			return Err(anyhow!(
+				"`words.source` is set to \"grammar\", but there is no \
+				grammar with an `{}` symbol.",
+				grammar::INVENTION
+			));
+		}
+
+		let weights = &config.visibility.weights;
+		if !weights.is_empty() {
+			WeightedIndex::new(weights.values())
+				.context("Invalid visibility weights.")?;
+		}
+		let every = &config.visibility.every;
+		if !every.is_empty() && !config.history.enable {
+			return Err(anyhow!(
+				"`visibility.every` needs `history.enable`, since \
+				that's where the last post of each visibility is \
+				found."
+			));
+		}
+
+		for (name, chance) in [
+			("english.plural", config.english.plural),
+			("english.definite", config.english.definite),
+			(
+				"spoiler.chance",
+				config.spoiler.as_ref().map_or(1.0, |s| s.chance),
+			),
+			("poll.chance", config.poll.chance),
+		] {
+			if !(0.0..=1.0).contains(&chance) {
+				return Err(anyhow!(
+					"`{name}` must be between 0 and 1, but is {chance}."
+				));
+			}
+		}
+
+		if config.templates.status.is_empty() {
+			config.templates.status.push(Template::new(
+				template::DEFAULT.to_owned(),
+				1,
+			)?);
+		}
+		WeightedIndex::new(
+			config.templates.status.iter().map(|t| t.weight),
+		)
+		.context("Invalid template weights.")?;
+		for template in &config.templates.status {
+			if !template.has(Slot::Invention) {
+				log::warn!(
+					"Template {:?} has no `{{invention}}` slot.",
+					template.text
+				);
+			}
+		}
+
+		// inventors and templates are fixed, so if they're blocked
+		// every post would be, and it's better to find out now
+		let blocklist = filter::Filter::new(&config)?;
+		let blocked = config
+			.inventors
+			.iter()
+			.map(|inventor| ("Inventor", &inventor.name))
+			.chain(
+				config
+					.templates
+					.status
+					.iter()
+					.map(|template| ("Template", &template.text)),
+			)
+			.find_map(|(kind, text)| {
+				Some((kind, text, blocklist.check(text)?))
+			});
+		if let Some((kind, text, pattern)) = blocked {
+			return Err(anyhow!(
+				"{kind} {text:?} matches the blocklist entry \
+				{pattern}."
+			));
+		}
+
+		if config
+			.card
+			.alt
+			.as_ref()
+			.is_some_and(|alt| alt.text.trim().is_empty())
+		{
+			return Err(anyhow!(
+				"`card.alt` is empty, but cards must have alt text."
+			));
+		}
+
+		if config.poll.enable {
+			let poll = &config.poll;
+			if !(2..=4).contains(&poll.options.len()) {
+				return Err(anyhow!(
+					"`poll.options` must have between 2 and 4 options."
+				));
+			}
+			if let Some(option) =
+				poll.options.iter().find(|o| o.chars().count() > 50)
+			{
+				return Err(anyhow!(
+					"Poll option {option:?} is longer than 50 characters."
+				));
+			}
+			// Mastodon allows polls between 5 minutes and a month long.
+			if !(1..=720).contains(&poll.hours) {
+				return Err(anyhow!(
+					"`poll.hours` must be between 1 and 720."
+				));
+			}
+			if !config.history.enable {
+				log::warn!(
+					"Poll results are only announced with `history.enable` \
+					on, since that's where open polls are kept track of."
+				);
+			}
+		}
+
+		let mastodon = config.backend == Backend::Mastodon;
+		if !mastodon && config.mentions.enable {
+			return Err(anyhow!(
+				"Answering mentions isn't supported on {:?} yet.",
+				config.backend
+			));
+		}
+		if config.backend == Backend::Misskey {
+			// a specified note with nobody specified is only seen by
+			// the bot itself
+			let mut visibilities = config.visibility.all();
+			if visibilities.any(|v| v == Visibility::Direct) {
+				return Err(anyhow!(
+					"Misskey has no direct posts that aren't to \
+					anyone, so `\"direct\"` can't be used."
+				));
+			}
+		}
+		if config.backend == Backend::Bluesky {
+			if config.bluesky.handle.is_none() {
+				return Err(anyhow!(
+					"`bluesky.handle` is needed to log in to Bluesky."
+				));
+			}
+			if config.token.is_some() || command.token.is_some() {
+				return Err(anyhow!(
+					"Bluesky logs in with an app password rather \
+					than a token, set `bluesky.password` instead."
+				));
+			}
+			if config.poll.enable {
+				return Err(anyhow!("Bluesky doesn't have polls."));
+			}
+			if config.spoiler.is_some() || config.sensitive {
+				log::warn!(
+					"Bluesky doesn't have content warnings, so \
+					`spoiler` and `sensitive` are ignored."
+				);
+			}
+		}
+
+		let bluesky = config.backend == Backend::Bluesky;
+		if config.retention.enabled() && !config.history.enable {
+			return Err(anyhow!(
+				"`retention` needs `history.enable`, since that's \
+				where the posts to delete are found."
+			));
+		}
+		if config.retention.enabled() && bluesky {
+			return Err(anyhow!(
+				"Deleting old posts isn't supported on Bluesky yet."
+			));
+		}
+
+		if let Some(profile) = &config.profile {
+			let slots =
+				[Slot::Inventor, Slot::Invention, Slot::Count];
+			let history = profile.bio.as_ref().is_some_and(|bio| {
+				slots.into_iter().any(|slot| bio.has(slot))
+			});
+			if history && !config.history.enable {
+				return Err(anyhow!(
+					"`profile.bio` is filled in from the history, so \
+					it needs `history.enable` to use `{{inventor}}`, \
+					`{{invention}}` or `{{count}}`."
+				));
+			}
+			if !mastodon {
+				return Err(anyhow!(
+					"Updating the profile isn't supported on {:?} \
+					yet.",
+					config.backend
+				));
+			}
+			let fields = profile.fields.as_ref().map_or(0, Vec::len);
+			if fields > profile::FIELDS {
+				return Err(anyhow!(
+					"`profile.fields` has {fields} fields, but only \
+					{} can be shown.",
+					profile::FIELDS
+				));
+			}
+			for path in [&profile.avatar, &profile.header]
+				.into_iter()
+				.flatten()
+			{
+				if profile::mime(path).is_none() {
+					return Err(anyhow!(
+						"Profile image {:?} isn't a PNG, JPEG, GIF \
+						or WebP.",
+						path
+					));
+				}
+			}
+		}
+
+		if config.stats.enable && !config.history.enable {
+			return Err(anyhow!(
+				"`stats.enable` needs `history.enable`, since \
+				that's where posts are kept track of."
+			));
+		}
+		if config.stats.adaptive && !config.stats.enable {
+			return Err(anyhow!(
+				"`stats.adaptive` needs `stats.enable`."
+			));
+		}
+		let floor = config.stats.floor;
+		if !(floor > 0.0 && floor <= 1.0) {
+			return Err(anyhow!(
+				"`stats.floor` must be above 0 and at most 1, \
+				but is {floor}."
+			));
+		}
+
+		if config.mentions.interval == 0 {
+			return Err(anyhow!(
+				"`mentions.interval` must be at least 1."
+			));
+		}
 
 		if config.cache.enable && !config.cache.protect {
 			log::warn!("You have chosen to cache the authorisation token, \
@@ -88,3 +1072,185 @@ impl Config {
 		Ok(config)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use clap::Parser;
+
+	use super::*;
+
+	/// Reads a config file with the given contents, and command line.
+	fn get(
+		name: &str,
+		file: &str,
+		args: &[&str],
+	) -> Result<Vec<Config>> {
+		let dir = std::env::temp_dir().join(format!(
+			"inventor_bot_config_{}",
+			std::process::id()
+		));
+		fs::create_dir_all(&dir).unwrap();
+		let path = dir.join(format!("{name}.toml"));
+		fs::write(&path, format!("cache.path = \"/cache\"\n{file}"))
+			.unwrap();
+		let path = path.to_str().unwrap();
+		let command = Command::try_parse_from(
+			["inventor_bot", "--config", path].iter().chain(args),
+		)
+		.unwrap();
+		Config::get(&command)
+	}
+
+	#[test]
+	fn accounts_build_on_the_shared_settings() {
+		let configs = get(
+			"merge",
+			"instance = \"example.com\"\n\
+			inventors = [\"Ada\"]\n\
+			poll.options = [\"Yes\", \"No\"]\n\
+			poll.hours = 12\n\
+			[[accounts]]\n\
+			name = \"one\"\n\
+			[[accounts]]\n\
+			name = \"two\"\n\
+			instance = \"example.org\"\n\
+			poll.hours = 48\n",
+			&[],
+		)
+		.unwrap();
+		let [one, two] = &configs[..] else {
+			panic!("{} accounts", configs.len());
+		};
+		assert_eq!(one.name.as_deref(), Some("one"));
+		assert_eq!(one.instance, "example.com");
+		assert_eq!(one.poll.hours, 12);
+		assert_eq!(one.cache.path, PathBuf::from("/cache/one"));
+		assert_eq!(two.instance, "example.org");
+		// tables are merged a key at a time
+		assert_eq!(two.poll.hours, 48);
+		assert_eq!(two.poll.options, ["Yes", "No"]);
+		assert_eq!(two.inventors.len(), 1);
+		assert_eq!(two.cache.path, PathBuf::from("/cache/two"));
+
+		let single =
+			get("single", "instance = \"example.com\"\n", &[])
+				.unwrap();
+		assert_eq!(single.len(), 1);
+		assert_eq!(single[0].name, None);
+		assert_eq!(single[0].cache.path, PathBuf::from("/cache"));
+	}
+
+	#[test]
+	fn accounts_are_checked() {
+		let accounts = |accounts: &str, args: &[&str]| {
+			get(
+				"checked",
+				&format!("instance = \"example.com\"\n{accounts}"),
+				args,
+			)
+		};
+		let fails = |list: &str, args: &[&str], error: &str| {
+			let e = accounts(list, args).unwrap_err().to_string();
+			assert!(e.contains(error), "{e}");
+		};
+		fails("accounts = []\n", &[], "must be a list of tables");
+		fails("[[accounts]]\n", &[], "must have a `name`");
+		for name in ["", ".hidden", "a/b"] {
+			let account = format!("[[accounts]]\nname = {name:?}\n");
+			fails(&account, &[], "can only contain");
+		}
+		let two = |a: &str, b: &str| {
+			format!(
+				"[[accounts]]\nname = \"{a}\"\n\
+				[[accounts]]\nname = \"{b}\"\n"
+			)
+		};
+		fails(&two("a", "a"), &[], "more than one account named");
+		fails("", &["--account", "a"], "has no `[[accounts]]`");
+
+		let picked =
+			accounts(&two("a", "b"), &["--account", "b"]).unwrap();
+		assert_eq!(picked.len(), 1);
+		assert_eq!(picked[0].name.as_deref(), Some("b"));
+		fails(
+			&two("a", "b"),
+			&["--account", "c"],
+			"no account named",
+		);
+		// a token can't be shared by every account
+		fails(&two("a", "b"), &["--token", "t"], "single account");
+	}
+
+	#[test]
+	fn cross_posts_are_checked() {
+		let crossposts = |accounts: &str| {
+			get(
+				"crosspost",
+				&format!("instance = \"example.com\"\n{accounts}"),
+				&[],
+			)
+		};
+		let account = |name: &str, rest: &str| {
+			format!("[[accounts]]\nname = \"{name}\"\n{rest}")
+		};
+		let bluesky = account(
+			"sky",
+			"backend = \"bluesky\"\nbluesky.handle = \"bot.test\"\n",
+		);
+		let fails = |accounts: &str, error: &str| {
+			let e = crossposts(accounts).unwrap_err().to_string();
+			assert!(e.contains(error), "{e}");
+		};
+
+		let configs = crossposts(&format!(
+			"{}{}{bluesky}",
+			account("main", "crosspost = [\"sky\"]\n"),
+			account("other", ""),
+		))
+		.unwrap();
+		assert_eq!(configs[0].crosspost, ["sky"]);
+
+		let e = get(
+			"crosspost_single",
+			"instance = \"example.com\"\ncrosspost = [\"a\"]\n",
+			&[],
+		)
+		.unwrap_err()
+		.to_string();
+		assert!(e.contains("only be used in `[[accounts]]`"), "{e}");
+		fails(
+			&account("main", "crosspost = [\"nobody\"]\n"),
+			"isn't another account",
+		);
+		fails(
+			&account("main", "crosspost = [\"main\"]\n"),
+			"isn't another account",
+		);
+		fails(
+			&format!(
+				"{}{}{bluesky}",
+				account("a", "crosspost = [\"sky\"]\n"),
+				account("b", "crosspost = [\"sky\"]\n"),
+			),
+			"More than one account cross-posts",
+		);
+		fails(
+			&format!(
+				"{}{}{bluesky}",
+				account("a", "crosspost = [\"b\"]\n"),
+				account("b", "crosspost = [\"sky\"]\n"),
+			),
+			"can't cross-post to other accounts itself",
+		);
+		// nothing hidden or behind a warning can go to Bluesky
+		for rest in [
+			"visibility = \"private\"\n",
+			"visibility.weights = { public = 1, direct = 1 }\n",
+			"spoiler = \"Hot take\"\n",
+		] {
+			let rest = format!("crosspost = [\"sky\"]\n{rest}");
+			let a = account("a", &rest);
+			fails(&format!("{a}{bluesky}"), "is on Bluesky");
+		}
+	}
+}
@@ -0,0 +1,383 @@
+use std::{collections::HashMap, fs};
+
+use anyhow::{Context, Result};
+use rand::Rng;
+use serde::Deserialize;
+use toml;
+
+use super::config::Config;
+
+// Nouns that usually don't take "a" or a plural.
+const MASS: &[&str] = &[
+	"bread",
+	"butter",
+	"cheese",
+	"chalk",
+	"chocolate",
+	"coffee",
+	"cutlery",
+	"electricity",
+	"furniture",
+	"glue",
+	"honey",
+	"information",
+	"ink",
+	"jam",
+	"lasagne",
+	"luggage",
+	"money",
+	"music",
+	"paper",
+	"pasta",
+	"pudding",
+	"rice",
+	"sand",
+	"shampoo",
+	"soup",
+	"sushi",
+	"tea",
+	"toothpaste",
+	"water",
+];
+
+// Nouns that are already plural, and have no singular form.
+const PLURAL: &[&str] = &[
+	"binoculars",
+	"clothes",
+	"fireworks",
+	"glasses",
+	"goggles",
+	"headphones",
+	"jeans",
+	"pants",
+	"pliers",
+	"scissors",
+	"shorts",
+	"sunglasses",
+	"tongs",
+	"trousers",
+];
+
+const IRREGULAR: &[(&str, &str)] = &[
+	("child", "children"),
+	("deer", "deer"),
+	("echo", "echoes"),
+	("fish", "fish"),
+	("foot", "feet"),
+	("goose", "geese"),
+	("hero", "heroes"),
+	("knife", "knives"),
+	("leaf", "leaves"),
+	("loaf", "loaves"),
+	("man", "men"),
+	("mouse", "mice"),
+	("ox", "oxen"),
+	("person", "people"),
+	("potato", "potatoes"),
+	("sheep", "sheep"),
+	("tomato", "tomatoes"),
+	("tooth", "teeth"),
+	("torpedo", "torpedoes"),
+	("volcano", "volcanoes"),
+	("wife", "wives"),
+	("woman", "women"),
+];
+
+// Words starting with a vowel letter but a consonant sound, and the
+// other way around.
+const A_PREFIXES: &[&str] = &[
+	"eu", "ewe", "one", "once", "uni", "use", "usu", "uti", "ura",
+];
+const AN_PREFIXES: &[&str] = &["heir", "honest", "honour", "hour"];
+
+// Words that already do the job of an article, which phrases from a
+// grammar might start with.
+const DETERMINERS: &[&str] = &[
+	"a", "an", "another", "any", "each", "every", "her", "his",
+	"its", "my", "no", "our", "some", "that", "the", "their",
+	"these", "this", "those", "your",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+	Countable,
+	Mass,
+	Plural,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Indefinite {
+	A,
+	An,
+}
+
+/// A user supplied correction for how a particular noun is inflected.
+#[derive(Debug, Deserialize)]
+pub struct Entry {
+	pub kind: Option<Kind>,
+	pub plural: Option<String>,
+	pub article: Option<Indefinite>,
+}
+
+#[derive(Debug)]
+pub struct Dictionary {
+	words: HashMap<String, Entry>,
+	plural: f64,
+	definite: f64,
+}
+
+impl Dictionary {
+	pub fn new(config: &Config) -> Result<Self> {
+		let words = match &config.english.dictionary {
+			Some(path) => {
+				let file =
+					fs::read_to_string(path).context(format!(
+						"Failed to read dictionary at {:?}.",
+						path
+					))?;
+				toml::de::from_str::<HashMap<String, Entry>>(&file)
+					.context(format!(
+						"Failed to parse dictionary at {:?}.",
+						path
+					))?
+					.into_iter()
+					.map(|(word, entry)| (word.to_lowercase(), entry))
+					.collect()
+			},
+			None => HashMap::new(),
+		};
+		Ok(Self {
+			words,
+			plural: config.english.plural,
+			definite: config.english.definite,
+		})
+	}
+
+	fn kind(&self, noun: &str) -> Kind {
+		if let Some(kind) = self.words.get(noun).and_then(|e| e.kind)
+		{
+			kind
+		} else if MASS.contains(&noun) {
+			Kind::Mass
+		} else if PLURAL.contains(&noun) {
+			Kind::Plural
+		} else {
+			Kind::Countable
+		}
+	}
+
+	fn indefinite(&self, word: &str) -> Indefinite {
+		let word = word.to_lowercase();
+		if let Some(article) =
+			self.words.get(&word).and_then(|e| e.article)
+		{
+			return article;
+		}
+		let prefixed = |prefixes: &[&str]| {
+			prefixes.iter().any(|p| word.starts_with(p))
+		};
+		if prefixed(AN_PREFIXES) {
+			Indefinite::An
+		} else if prefixed(A_PREFIXES) {
+			Indefinite::A
+		} else if word.starts_with(['a', 'e', 'i', 'o', 'u']) {
+			Indefinite::An
+		} else {
+			Indefinite::A
+		}
+	}
+
+	fn plural(&self, noun: &str) -> String {
+		let lower = noun.to_lowercase();
+		if let Some(plural) =
+			self.words.get(&lower).and_then(|e| e.plural.clone())
+		{
+			return plural;
+		}
+		if let Some((_, plural)) =
+			IRREGULAR.iter().find(|(singular, _)| *singular == lower)
+		{
+			return plural.to_string();
+		}
+		if ["s", "x", "z", "ch", "sh"]
+			.iter()
+			.any(|s| lower.ends_with(s))
+		{
+			format!("{noun}es")
+		} else if lower.ends_with('y')
+			&& !["ay", "ey", "iy", "oy", "uy"]
+				.iter()
+				.any(|s| lower.ends_with(s))
+		{
+			format!("{}ies", &noun[..noun.len() - 1])
+		} else {
+			format!("{noun}s")
+		}
+	}
+
+	/// Adds an article to a noun phrase, and possibly makes it plural.
+	/// The article depends on the first word, and everything else
+	/// depends on the last word, so "haunted toaster" may become
+	/// "a haunted toaster" or "the haunted toasters". Phrases which
+	/// already start with an article, or something like "my" or
+	/// "Ada's", are left as they are.
+	pub fn inflect(
+		&self,
+		phrase: &str,
+		rng: &mut impl Rng,
+	) -> String {
+		let first = phrase.split(' ').next().unwrap_or_default();
+		let first = first.to_lowercase();
+		if phrase.contains(' ')
+			&& (DETERMINERS.contains(&first.as_str())
+				|| first.ends_with("'s")
+				|| first.ends_with("s'"))
+		{
+			return phrase.to_owned();
+		}
+		let (rest, noun) = match phrase.rsplit_once(' ') {
+			Some((rest, noun)) => (Some(rest), noun),
+			None => (None, phrase),
+		};
+		let mut kind = self.kind(&noun.to_lowercase());
+		let noun = match kind {
+			Kind::Countable if rng.gen_bool(self.plural) => {
+				kind = Kind::Plural;
+				self.plural(noun)
+			},
+			_ => noun.to_owned(),
+		};
+		let phrase = match rest {
+			Some(rest) => format!("{rest} {noun}"),
+			None => noun,
+		};
+		if rng.gen_bool(self.definite) {
+			return format!("the {phrase}");
+		}
+		match kind {
+			Kind::Countable => {
+				let first =
+					phrase.split(' ').next().unwrap_or(&phrase);
+				match self.indefinite(first) {
+					Indefinite::A => format!("a {phrase}"),
+					Indefinite::An => format!("an {phrase}"),
+				}
+			},
+			Kind::Mass | Kind::Plural => match rng.gen::<bool>() {
+				true => format!("some {phrase}"),
+				false => phrase,
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::{rngs::StdRng, SeedableRng};
+
+	use super::*;
+
+	fn dictionary(plural: f64, definite: f64) -> Dictionary {
+		Dictionary {
+			words: HashMap::new(),
+			plural,
+			definite,
+		}
+	}
+
+	#[test]
+	fn plurals() {
+		let dictionary = dictionary(0.0, 0.0);
+		for (singular, plural) in [
+			("toaster", "toasters"),
+			("box", "boxes"),
+			("bus", "buses"),
+			("watch", "watches"),
+			("brush", "brushes"),
+			("waltz", "waltzes"),
+			("pony", "ponies"),
+			("monkey", "monkeys"),
+			("child", "children"),
+			("sheep", "sheep"),
+			("potato", "potatoes"),
+			("Knife", "knives"),
+		] {
+			assert_eq!(dictionary.plural(singular), plural);
+		}
+	}
+
+	#[test]
+	fn overridden_plural() {
+		let mut dictionary = dictionary(0.0, 0.0);
+		dictionary.words.insert(
+			String::from("octopus"),
+			Entry {
+				kind: None,
+				plural: Some(String::from("octopodes")),
+				article: None,
+			},
+		);
+		assert_eq!(dictionary.plural("octopus"), "octopodes");
+	}
+
+	#[test]
+	fn articles() {
+		let dictionary = dictionary(0.0, 0.0);
+		let mut rng = StdRng::seed_from_u64(0);
+		for (noun, inflected) in [
+			("toaster", "a toaster"),
+			("egg", "an egg"),
+			("umbrella", "an umbrella"),
+			("unicycle", "a unicycle"),
+			("euphonium", "a euphonium"),
+			("hourglass", "an hourglass"),
+			("haunted toaster", "a haunted toaster"),
+			("old hat", "an old hat"),
+		] {
+			assert_eq!(dictionary.inflect(noun, &mut rng), inflected);
+		}
+	}
+
+	#[test]
+	fn only_the_last_word_is_plural() {
+		let dictionary = dictionary(1.0, 1.0);
+		let mut rng = StdRng::seed_from_u64(0);
+		assert_eq!(
+			dictionary.inflect("haunted mouse", &mut rng),
+			"the haunted mice"
+		);
+	}
+
+	#[test]
+	fn mass_and_plural_nouns_stay_as_they_are() {
+		let dictionary = dictionary(1.0, 0.0);
+		let mut rng = StdRng::seed_from_u64(0);
+		for noun in ["soup", "scissors"] {
+			for _ in 0..10 {
+				let inflected = dictionary.inflect(noun, &mut rng);
+				assert!(
+					[noun.to_owned(), format!("some {noun}")]
+						.contains(&inflected),
+					"{inflected}"
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn phrases_with_determiners_stay_as_they_are() {
+		let dictionary = dictionary(1.0, 1.0);
+		let mut rng = StdRng::seed_from_u64(0);
+		for phrase in [
+			"the haunted toaster",
+			"A haunted toaster",
+			"my left sock",
+			"Ada's loom",
+			"some cheese",
+		] {
+			assert_eq!(dictionary.inflect(phrase, &mut rng), phrase);
+		}
+	}
+}
@@ -0,0 +1,141 @@
+use anyhow::{Context, Result};
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use toml;
+
+use super::config::Config;
+
+const BUNDLED: &str = include_str!("../../assets/blocklist.toml");
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Blocklist {
+	#[serde(default)]
+	pub exact: Vec<String>,
+	#[serde(default)]
+	pub contains: Vec<String>,
+	#[serde(default)]
+	pub regex: Vec<String>,
+}
+
+pub struct Filter {
+	patterns: Vec<(String, Regex)>,
+}
+
+impl Filter {
+	pub fn new(config: &Config) -> Result<Self> {
+		let mut patterns = Vec::new();
+		if config.filter.default {
+			let bundled = toml::de::from_str::<Blocklist>(BUNDLED)
+				.expect("The bundled blocklist should be valid.");
+			add(&mut patterns, &bundled)?;
+		}
+		add(&mut patterns, &config.filter.blocklist)
+			.context("Invalid blocklist in config.")?;
+		Ok(Self { patterns })
+	}
+
+	/// Returns the first pattern matched by the text, if any.
+	pub fn check(&self, text: &str) -> Option<&str> {
+		self.patterns
+			.iter()
+			.find(|(_, regex)| regex.is_match(text))
+			.map(|(pattern, _)| pattern.as_str())
+	}
+}
+
+fn add(
+	patterns: &mut Vec<(String, Regex)>,
+	blocklist: &Blocklist,
+) -> Result<()> {
+	// exact words also match their plurals, which is how inventions
+	// are often inflected
+	let exact = blocklist.exact.iter().map(|word| {
+		let pattern = format!(r"\b{}(?:e?s)?\b", regex::escape(word));
+		(format!("{word:?}"), pattern)
+	});
+	let contains = blocklist
+		.contains
+		.iter()
+		.map(|text| (format!("*{text}*"), regex::escape(text)));
+	let regex = blocklist
+		.regex
+		.iter()
+		.map(|re| (format!("/{re}/"), re.clone()));
+	for (name, pattern) in exact.chain(contains).chain(regex) {
+		let regex = RegexBuilder::new(&pattern)
+			.case_insensitive(true)
+			.build()
+			.context(format!("Invalid pattern {name}"))?;
+		patterns.push((name, regex));
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn filter(blocklist: Blocklist) -> Filter {
+		let mut patterns = Vec::new();
+		add(&mut patterns, &blocklist).unwrap();
+		Filter { patterns }
+	}
+
+	#[test]
+	fn exact_words_match_whole_words() {
+		let filter = filter(Blocklist {
+			exact: vec![String::from("gun")],
+			..Blocklist::default()
+		});
+		assert_eq!(filter.check("a gun"), Some("\"gun\""));
+		assert_eq!(filter.check("GUN!"), Some("\"gun\""));
+		assert_eq!(filter.check("it has begun"), None);
+		assert_eq!(filter.check("gunpowder"), None);
+	}
+
+	#[test]
+	fn exact_words_match_plurals() {
+		let filter = filter(Blocklist {
+			exact: vec![String::from("bomb"), String::from("corpse")],
+			..Blocklist::default()
+		});
+		assert_eq!(filter.check("Bombs"), Some("\"bomb\""));
+		assert_eq!(filter.check("the corpses"), Some("\"corpse\""));
+		assert_eq!(filter.check("bombastic"), None);
+	}
+
+	#[test]
+	fn contains_matches_inside_words() {
+		let filter = filter(Blocklist {
+			contains: vec![String::from("gun")],
+			..Blocklist::default()
+		});
+		assert_eq!(filter.check("it has begun"), Some("*gun*"));
+	}
+
+	#[test]
+	fn regex_patterns() {
+		let filter = filter(Blocklist {
+			regex: vec![String::from(r"^the \w+ machine$")],
+			..Blocklist::default()
+		});
+		assert!(filter.check("The doom machine").is_some());
+		assert!(filter.check("a doom machine").is_none());
+	}
+
+	#[test]
+	fn invalid_regex() {
+		let blocklist = Blocklist {
+			regex: vec![String::from("(")],
+			..Blocklist::default()
+		};
+		assert!(add(&mut Vec::new(), &blocklist).is_err());
+	}
+
+	#[test]
+	fn bundled_blocklist_is_valid() {
+		let bundled =
+			toml::de::from_str::<Blocklist>(BUNDLED).unwrap();
+		add(&mut Vec::new(), &bundled).unwrap();
+	}
+}
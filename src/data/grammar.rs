@@ -0,0 +1,289 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use rand::{seq::SliceRandom, Rng};
+use serde::Deserialize;
+use toml;
+
+/// The symbol expanded to generate inventions.
+pub const INVENTION: &str = "invention";
+
+#[derive(Debug, Clone)]
+enum Part {
+	Text(String),
+	Symbol(String),
+}
+
+/// A single expansion of a symbol, e.g. `#adjective# #noun#`.
+#[derive(Debug, Clone)]
+pub struct Rule(Vec<Part>);
+
+impl Rule {
+	/// `#name#` expands the symbol `name`, and `\#` is a literal `#`.
+	pub fn parse(text: &str) -> Result<Self> {
+		let mut parts = Vec::new();
+		let mut literal = String::new();
+		let mut symbol: Option<String> = None;
+		let mut chars = text.chars();
+		while let Some(c) = chars.next() {
+			match (c, &mut symbol) {
+				('\\', _) => match chars.next() {
+					Some(c) => match &mut symbol {
+						Some(name) => name.push(c),
+						None => literal.push(c),
+					},
+					None => literal.push(c),
+				},
+				('#', None) => {
+					if !literal.is_empty() {
+						parts.push(Part::Text(std::mem::take(
+							&mut literal,
+						)));
+					}
+					symbol = Some(String::new());
+				},
+				('#', Some(name)) => {
+					if name.is_empty() {
+						return Err(anyhow!(
+							"Empty symbol in {text:?}"
+						));
+					}
+					parts.push(Part::Symbol(std::mem::take(name)));
+					symbol = None;
+				},
+				(c, Some(name)) => name.push(c),
+				(c, None) => literal.push(c),
+			}
+		}
+		if let Some(name) = symbol {
+			return Err(anyhow!(
+				"Unclosed symbol `#{name}` in {text:?}"
+			));
+		}
+		if !literal.is_empty() {
+			parts.push(Part::Text(literal));
+		}
+		Ok(Self(parts))
+	}
+
+	pub fn symbols(&self) -> impl Iterator<Item = &str> {
+		self.0.iter().filter_map(|part| match part {
+			Part::Symbol(name) => Some(name.as_str()),
+			Part::Text(_) => None,
+		})
+	}
+
+	pub fn has_symbols(&self) -> bool {
+		self.symbols().next().is_some()
+	}
+
+	pub fn expand(
+		&self,
+		grammar: &Grammar,
+		rng: &mut impl Rng,
+	) -> String {
+		let mut out = String::new();
+		for part in &self.0 {
+			match part {
+				Part::Text(text) => out.push_str(text),
+				Part::Symbol(name) => {
+					out.push_str(&grammar.expand(name, rng))
+				},
+			}
+		}
+		out
+	}
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawRules {
+	One(String),
+	Many(Vec<String>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Grammar {
+	rules: HashMap<String, Vec<Rule>>,
+}
+
+impl Grammar {
+	/// Loads a grammar from either a Tracery style JSON file, or a TOML
+	/// file, depending on the extension.
+	pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+		let path = path.as_ref();
+		let file = fs::read_to_string(path).context(format!(
+			"Failed to read grammar at {:?}.",
+			path
+		))?;
+		let raw: HashMap<String, RawRules> =
+			match path.extension().and_then(|e| e.to_str()) {
+				Some("json") => serde_json::from_str(&file)?,
+				_ => toml::de::from_str(&file)?,
+			};
+		Self::new(raw)
+	}
+
+	fn new(raw: HashMap<String, RawRules>) -> Result<Self> {
+		let mut rules = HashMap::new();
+		for (symbol, raw) in raw {
+			let raw = match raw {
+				RawRules::One(rule) => vec![rule],
+				RawRules::Many(rules) => rules,
+			};
+			if raw.is_empty() {
+				return Err(anyhow!(
+					"Symbol `{symbol}` has no rules."
+				));
+			}
+			let parsed = raw
+				.iter()
+				.map(|rule| Rule::parse(rule))
+				.collect::<Result<Vec<_>>>()?;
+			rules.insert(symbol, parsed);
+		}
+		let grammar = Self { rules };
+		grammar.check()?;
+		Ok(grammar)
+	}
+
+	pub fn has(&self, symbol: &str) -> bool {
+		self.rules.contains_key(symbol)
+	}
+
+	/// Checks that every symbol used is defined, and that no symbol can
+	/// expand to itself, which could otherwise go on forever.
+	fn check(&self) -> Result<()> {
+		#[derive(Clone, Copy, PartialEq)]
+		enum State {
+			Visiting,
+			Done,
+		}
+		fn visit<'a>(
+			grammar: &'a Grammar,
+			symbol: &'a str,
+			states: &mut HashMap<&'a str, State>,
+			path: &mut Vec<&'a str>,
+		) -> Result<()> {
+			match states.get(symbol) {
+				Some(State::Done) => return Ok(()),
+				Some(State::Visiting) => {
+					let start = path
+						.iter()
+						.position(|s| *s == symbol)
+						.unwrap();
+					let cycle = path[start..]
+						.iter()
+						.chain([&symbol])
+						.map(|s| format!("#{s}#"))
+						.collect::<Vec<_>>()
+						.join(" -> ");
+					return Err(anyhow!(
+						"Grammar has a cycle: {cycle}"
+					));
+				},
+				None => {},
+			}
+			states.insert(symbol, State::Visiting);
+			path.push(symbol);
+			for name in
+				grammar.rules[symbol].iter().flat_map(Rule::symbols)
+			{
+				if !grammar.has(name) {
+					return Err(anyhow!(
+						"Symbol `{name}` is used by `{symbol}`, \
+						but is never defined."
+					));
+				}
+				visit(grammar, name, states, path)?;
+			}
+			path.pop();
+			states.insert(symbol, State::Done);
+			Ok(())
+		}
+
+		let mut states = HashMap::new();
+		for symbol in self.rules.keys() {
+			visit(self, symbol, &mut states, &mut Vec::new())?;
+		}
+		Ok(())
+	}
+
+	pub fn expand(&self, symbol: &str, rng: &mut impl Rng) -> String {
+		// check() guarantees every symbol exists and is non-empty.
+		self.rules[symbol].choose(rng).unwrap().expand(self, rng)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::{rngs::StdRng, SeedableRng};
+
+	use super::*;
+
+	fn grammar(json: &str) -> Result<Grammar> {
+		Grammar::new(serde_json::from_str(json).unwrap())
+	}
+
+	#[test]
+	fn parses_symbols() {
+		let rule = Rule::parse(r"#adjective# \#1 #noun#").unwrap();
+		assert_eq!(
+			rule.symbols().collect::<Vec<_>>(),
+			["adjective", "noun"]
+		);
+		assert!(!Rule::parse("plain").unwrap().has_symbols());
+	}
+
+	#[test]
+	fn bad_rules() {
+		assert!(Rule::parse("#unclosed").is_err());
+		assert!(Rule::parse("empty ## symbol").is_err());
+	}
+
+	#[test]
+	fn expands_nested_rules() {
+		let grammar = grammar(
+			r##"{
+				"invention": "#adjective# #noun#",
+				"adjective": ["#colour#"],
+				"colour": "red",
+				"noun": ["toaster"]
+			}"##,
+		)
+		.unwrap();
+		let mut rng = StdRng::seed_from_u64(0);
+		assert_eq!(
+			grammar.expand(INVENTION, &mut rng),
+			"red toaster"
+		);
+	}
+
+	#[test]
+	fn recursive_rules() {
+		let e = grammar(
+			r##"{
+				"invention": "#a#",
+				"a": ["#b#", "stop"],
+				"b": "#a#"
+			}"##,
+		)
+		.unwrap_err()
+		.to_string();
+		assert!(e.contains("cycle"), "{e}");
+		assert!(grammar(r##"{ "a": "more #a#" }"##).is_err());
+	}
+
+	#[test]
+	fn undefined_symbols() {
+		let e = grammar(r##"{ "invention": "#missing#" }"##)
+			.unwrap_err()
+			.to_string();
+		assert!(e.contains("`missing`"), "{e}");
+	}
+
+	#[test]
+	fn empty_symbols() {
+		assert!(grammar(r##"{ "invention": [] }"##).is_err());
+	}
+}
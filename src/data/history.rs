@@ -0,0 +1,258 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use toml;
+
+use super::config::{Config, Source, Visibility};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+	pub timestamp: DateTime<Utc>,
+	pub inventor: String,
+	pub invention: String,
+	pub template: String,
+	pub status: String,
+	pub id: String,
+	pub url: Option<String>,
+	#[serde(default)]
+	pub visibility: Option<Visibility>,
+	#[serde(default)]
+	pub in_reply_to: Option<String>,
+	#[serde(default)]
+	pub poll: Option<Poll>,
+	/// The id is of a scheduled status rather than a real one.
+	#[serde(default)]
+	pub scheduled: bool,
+	/// The post has since been deleted. It's kept in the history so
+	/// that it still counts towards repeats and the post count.
+	#[serde(default)]
+	pub deleted: bool,
+	/// The inventor as written in the config, when `inventor` was
+	/// expanded from the grammar.
+	#[serde(default)]
+	pub rule: Option<String>,
+	/// Where the word for the invention came from.
+	#[serde(default)]
+	pub source: Option<Source>,
+	#[serde(default)]
+	pub engagement: Option<Engagement>,
+}
+
+/// How people have interacted with a post, as of when it was checked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Engagement {
+	pub favourites: u64,
+	pub reblogs: u64,
+	pub replies: u64,
+	pub checked: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Poll {
+	pub id: String,
+	pub expires_at: DateTime<Utc>,
+	/// The id of the post announcing the result, once there is one.
+	pub result: Option<String>,
+}
+
+impl Entry {
+	/// The inventor in the config this post was made by.
+	pub fn persona(&self) -> &str {
+		self.rule.as_deref().unwrap_or(&self.inventor)
+	}
+
+	pub fn matches(&self, query: &str) -> bool {
+		let query = query.to_lowercase();
+		[&self.inventor, &self.invention, &self.status]
+			.iter()
+			.any(|field| field.to_lowercase().contains(&query))
+	}
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct History {
+	#[serde(default, rename = "post")]
+	pub posts: Vec<Entry>,
+	#[serde(skip)]
+	path: Option<PathBuf>,
+}
+
+impl History {
+	pub fn load(config: &Config) -> Result<Self> {
+		if !config.history.enable {
+			return Ok(Self::default());
+		}
+		let path = config.cache.path.join("history.toml");
+		let mut history = match super::exists(&path)? {
+			true => {
+				let file = fs::read_to_string(&path).context(
+					format!("Failed to read history at {:?}.", path),
+				)?;
+				toml::de::from_str::<Self>(&file).context(format!(
+					"Failed to parse history at {:?}.",
+					path
+				))?
+			},
+			false => Self::default(),
+		};
+		history.path = Some(path);
+		Ok(history)
+	}
+
+	/// Saves the history. This isn't affected by `cache.enable`, only by
+	/// `history.enable`, since so much relies on the history.
+	pub fn dump(&self) -> Result<()> {
+		let Some(path) = &self.path else {
+			return Ok(());
+		};
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		let data = toml::to_string(&self)?;
+		fs::write(path, data)
+			.context(format!("Failed to save history at {:?}.", path))
+	}
+
+	pub fn push(&mut self, entry: Entry) -> Result<()> {
+		self.posts.push(entry);
+		self.dump()
+	}
+
+	pub fn print(&self, query: Option<&str>, limit: Option<usize>) {
+		let posts = self
+			.posts
+			.iter()
+			.filter(|entry| query.is_none_or(|q| entry.matches(q)))
+			.collect::<Vec<_>>();
+		let skip = limit.map_or(0, |n| posts.len().saturating_sub(n));
+		for entry in &posts[skip..] {
+			println!(
+				"[{}] {}{}",
+				entry.timestamp.format("%Y-%m-%d %H:%M"),
+				entry.status,
+				match (entry.scheduled, entry.deleted) {
+					(_, true) => " (deleted)",
+					(true, false) => " (scheduled)",
+					(false, false) => "",
+				}
+			);
+			if let Some(url) = &entry.url {
+				println!("    {url}");
+			}
+		}
+	}
+
+	/// When a post with the given visibility was last made.
+	pub fn last(
+		&self,
+		visibility: Visibility,
+	) -> Option<DateTime<Utc>> {
+		self.posts
+			.iter()
+			.rev()
+			.find(|entry| entry.visibility == Some(visibility))
+			.map(|entry| entry.timestamp)
+	}
+
+	/// Posts with polls that haven't had their result announced yet.
+	pub fn open_polls(&self) -> impl Iterator<Item = &Entry> {
+		self.posts.iter().filter(|entry| {
+			let poll = entry.poll.as_ref();
+			!entry.deleted
+				&& !entry.scheduled
+				&& poll.is_some_and(|p| p.result.is_none())
+		})
+	}
+
+	/// Whether the inventor and invention have already been posted
+	/// together recently enough to break the configured repeat rule.
+	pub fn is_repeat(
+		&self,
+		config: &Config,
+		inventor: &str,
+		invention: &str,
+	) -> bool {
+		let rule = &config.history;
+		let since = rule
+			.avoid_days
+			.map(|days| Utc::now() - Duration::days(days as i64));
+		let skip = rule
+			.avoid_posts
+			.map(|n| self.posts.len().saturating_sub(n));
+		self.posts
+			.iter()
+			.enumerate()
+			.filter(|(i, entry)| {
+				let by_posts = skip.is_some_and(|skip| *i >= skip);
+				let by_days = since
+					.is_some_and(|since| entry.timestamp >= since);
+				by_posts || by_days
+			})
+			.any(|(_, entry)| {
+				entry.inventor.eq_ignore_ascii_case(inventor)
+					&& entry.invention.eq_ignore_ascii_case(invention)
+			})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn entry(inventor: &str, invention: &str, days: i64) -> Entry {
+		let timestamp = Utc::now() - Duration::days(days);
+		toml::from_str(&format!(
+			"timestamp = {:?}\n\
+			inventor = {inventor:?}\n\
+			invention = {invention:?}\n\
+			template = \"\"\nstatus = \"\"\nid = \"\"\n",
+			timestamp.to_rfc3339()
+		))
+		.unwrap()
+	}
+
+	fn rule(history: &str) -> Config {
+		toml::from_str(&format!(
+			"instance = \"example.com\"\ncache.path = \"/cache\"\n\
+			[history]\n{history}"
+		))
+		.unwrap()
+	}
+
+	#[test]
+	fn repeats() {
+		let history = History {
+			posts: vec![
+				entry("Ada", "the loom", 10),
+				entry("Grace", "the compiler", 3),
+				entry("Alan", "the machine", 0),
+			],
+			path: None,
+		};
+
+		// with no rule, nothing counts as a repeat
+		let config = rule("");
+		assert!(!history.is_repeat(&config, "Alan", "the machine"));
+
+		let config = rule("avoid_posts = 2\n");
+		assert!(history.is_repeat(&config, "Grace", "the compiler"));
+		// case doesn't matter
+		assert!(history.is_repeat(&config, "alan", "The Machine"));
+		assert!(!history.is_repeat(&config, "Ada", "the loom"));
+		// it has to be the same inventor and invention together
+		assert!(!history.is_repeat(&config, "Grace", "the machine"));
+
+		let config = rule("avoid_days = 5\n");
+		assert!(history.is_repeat(&config, "Grace", "the compiler"));
+		assert!(!history.is_repeat(&config, "Ada", "the loom"));
+
+		// either rule is enough
+		let config = rule("avoid_posts = 1\navoid_days = 5\n");
+		assert!(history.is_repeat(&config, "Grace", "the compiler"));
+		assert!(!history.is_repeat(&config, "Ada", "the loom"));
+		let config = rule("avoid_posts = 3\navoid_days = 1\n");
+		assert!(history.is_repeat(&config, "Ada", "the loom"));
+	}
+}
@@ -0,0 +1,197 @@
+use std::{fs, path::PathBuf, sync::OnceLock};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use toml;
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::config::Config;
+
+/// The software an instance runs, as it describes itself in NodeInfo.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Software {
+	/// Always lowercase, e.g. `"mastodon"` or `"akkoma"`.
+	pub name: String,
+	#[serde(default)]
+	pub version: String,
+}
+
+/// What an instance can do, which decides how posts are sent to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Features {
+	/// The formats a post's `content_type` can be. If this is empty,
+	/// the instance doesn't take a content type at all.
+	pub content_types: Vec<String>,
+	/// The most characters a post can have, if known.
+	pub max_length: Option<usize>,
+	/// How many characters each link counts as, on instances which
+	/// count them all as the same length.
+	#[serde(default)]
+	pub url_length: Option<usize>,
+	/// Whether length is counted in graphemes, the characters as they
+	/// look, like on Bluesky, rather than in code points.
+	#[serde(default)]
+	pub graphemes: bool,
+	pub polls: bool,
+}
+
+impl Default for Features {
+	/// What's assumed of an instance whose software isn't known,
+	/// which is what the bot has always assumed.
+	fn default() -> Self {
+		Self {
+			content_types: vec![String::from("text/plain")],
+			max_length: None,
+			url_length: None,
+			graphemes: false,
+			polls: true,
+		}
+	}
+}
+
+impl Features {
+	/// The content type to send plain text posts with, if the
+	/// instance takes one.
+	pub fn content_type(&self) -> Option<&'static str> {
+		self.content_types
+			.iter()
+			.any(|t| t == "text/plain")
+			.then_some("text/plain")
+	}
+
+	/// How long the instance counts some text as. Where links all
+	/// count as the same length, as on Mastodon, mentions of other
+	/// instances only count the username too.
+	fn length(&self, text: &str) -> usize {
+		static LINK: OnceLock<Regex> = OnceLock::new();
+		static MENTION: OnceLock<Regex> = OnceLock::new();
+		let mut length = match self.graphemes {
+			true => text.graphemes(true).count(),
+			false => text.chars().count(),
+		};
+		let Some(url_length) = self.url_length else {
+			return length;
+		};
+		let link = LINK
+			.get_or_init(|| Regex::new(r"https?://[^\s]+").unwrap());
+		let mention = MENTION.get_or_init(|| {
+			Regex::new(r"(?:^|\s)@\w+(@[\w.-]+\w)").unwrap()
+		});
+		for found in link.find_iter(text) {
+			length =
+				length - found.as_str().chars().count() + url_length;
+		}
+		for caps in mention.captures_iter(text) {
+			length -= caps[1].chars().count();
+		}
+		length
+	}
+
+	/// Whether some text is short enough to post.
+	pub fn fits(&self, text: &str) -> bool {
+		self.max_length.is_none_or(|max| self.length(text) <= max)
+	}
+}
+
+/// What was found out about an instance the last time it was asked,
+/// so that it's still known if it can't be asked on a later run.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Instance {
+	pub software: Software,
+	pub features: Features,
+	pub checked: DateTime<Utc>,
+	#[serde(skip)]
+	path: PathBuf,
+}
+
+impl Instance {
+	pub fn new(
+		config: &Config,
+		software: Software,
+		features: Features,
+	) -> Self {
+		Self {
+			software,
+			features,
+			checked: Utc::now(),
+			path: path(config),
+		}
+	}
+
+	pub fn load(config: &Config) -> Result<Option<Self>> {
+		let path = path(config);
+		if !super::exists(&path)? {
+			return Ok(None);
+		}
+		let file = fs::read_to_string(&path).context(format!(
+			"Failed to read instance details at {:?}.",
+			path
+		))?;
+		let mut instance = toml::de::from_str::<Self>(&file)
+			.context(format!(
+				"Failed to parse instance details at {:?}.",
+				path
+			))?;
+		instance.path = path;
+		Ok(Some(instance))
+	}
+
+	/// Saves what was found out. Like the history, this is kept
+	/// whether or not `cache.enable` is on.
+	pub fn dump(&self) -> Result<()> {
+		if let Some(dir) = self.path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		let data = toml::to_string(&self)?;
+		fs::write(&self.path, data).context(format!(
+			"Failed to save instance details at {:?}.",
+			self.path
+		))
+	}
+}
+
+fn path(config: &Config) -> PathBuf {
+	config.cache.path.join("instance.toml")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn lengths() {
+		let plain = Features::default();
+		assert_eq!(plain.length("héllo"), 5);
+		// the flag and the accented e are two code points each, but
+		// only one grapheme
+		assert_eq!(plain.length("🇳🇿 e\u{301}"), 5);
+		let bluesky = Features {
+			graphemes: true,
+			..Features::default()
+		};
+		assert_eq!(bluesky.length("🇳🇿 e\u{301}"), 3);
+
+		let mastodon = Features {
+			url_length: Some(23),
+			..Features::default()
+		};
+		let link = "see https://example.com/a/very/long/path/indeed";
+		assert_eq!(mastodon.length(link), 4 + 23);
+		assert_eq!(plain.length(link), link.chars().count());
+		// mentions of other instances only count the username
+		assert_eq!(mastodon.length("hi @ada@example.com"), 7);
+		assert_eq!(mastodon.length("hi @ada"), 7);
+		// but an email address isn't a mention
+		assert_eq!(mastodon.length("ada@example.com"), 15);
+
+		let limited = Features {
+			max_length: Some(5),
+			..Features::default()
+		};
+		assert!(limited.fits("hello"));
+		assert!(!limited.fits("hello!"));
+		assert!(plain.fits(&"a".repeat(100_000)));
+	}
+}
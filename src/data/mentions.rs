@@ -0,0 +1,86 @@
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use toml;
+
+use super::config::Config;
+
+/// What the bot needs to remember between runs to answer mentions.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Mentions {
+	/// The id of the newest notification that has been dealt with.
+	pub last_seen: Option<String>,
+	/// How many times answering the mention after `last_seen` has
+	/// failed, so that one which keeps failing is given up on.
+	#[serde(default)]
+	pub failures: u32,
+	/// When each account was last replied to, for rate limiting.
+	#[serde(default)]
+	replies: BTreeMap<String, Vec<DateTime<Utc>>>,
+	#[serde(skip)]
+	path: PathBuf,
+}
+
+impl Mentions {
+	pub fn load(config: &Config) -> Result<Self> {
+		let path = config.cache.path.join("mentions.toml");
+		let mut mentions = match super::exists(&path)? {
+			true => {
+				let file = fs::read_to_string(&path).context(
+					format!("Failed to read mentions at {:?}.", path),
+				)?;
+				toml::de::from_str::<Self>(&file).context(format!(
+					"Failed to parse mentions at {:?}.",
+					path
+				))?
+			},
+			false => Self::default(),
+		};
+		mentions.path = path;
+		Ok(mentions)
+	}
+
+	pub fn dump(&self) -> Result<()> {
+		if let Some(dir) = self.path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		let data = toml::to_string(&self)?;
+		fs::write(&self.path, data).context(format!(
+			"Failed to save mentions at {:?}.",
+			self.path
+		))
+	}
+
+	/// Whether `acct` has already had as many replies as they're
+	/// allowed within the window.
+	pub fn is_limited(
+		&mut self,
+		config: &Config,
+		acct: &str,
+	) -> bool {
+		let since = Utc::now()
+			- Duration::minutes(config.mentions.window as i64);
+		self.replies.retain(|_, times| {
+			times.retain(|time| *time >= since);
+			!times.is_empty()
+		});
+		self.replies
+			.get(acct)
+			.is_some_and(|times| times.len() >= config.mentions.limit)
+	}
+
+	/// Marks a notification as dealt with.
+	pub fn seen(&mut self, id: String) {
+		self.last_seen = Some(id);
+		self.failures = 0;
+	}
+
+	pub fn replied(&mut self, acct: &str) {
+		self.replies
+			.entry(acct.to_owned())
+			.or_default()
+			.push(Utc::now());
+	}
+}
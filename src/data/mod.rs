@@ -1,6 +1,16 @@
 pub mod cache;
+pub mod card;
 pub mod config;
+pub mod english;
+pub mod filter;
+pub mod grammar;
+pub mod history;
+pub mod instance;
+pub mod mentions;
+pub mod pending;
+pub mod profile;
 pub mod secrets;
+pub mod template;
 
 use std::{
 	fs::OpenOptions,
@@ -34,7 +44,7 @@ pub fn is_valid(path: impl AsRef<Path>, secure: bool) -> Result<()> {
 	#[cfg(unix)]
 	if secure {
 		use std::os::unix::fs::OpenOptionsExt;
-		opts.mode(600);
+		opts.mode(0o600);
 		// is there a windows equivalent to this?
 	}
 
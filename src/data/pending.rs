@@ -0,0 +1,136 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use toml;
+
+use super::{config::Config, history::Entry};
+
+/// A post that has been saved before being sent, so that if the bot
+/// stops before it knows whether the post was made, it can be sent
+/// again with the same key on the next run.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Pending {
+	/// Sent as the `Idempotency-Key`, so the instance only makes the
+	/// post once, however many times it is sent.
+	pub key: String,
+	/// The request body, exactly as it was first sent.
+	pub body: String,
+	pub created: DateTime<Utc>,
+	/// What to record in the history once the post is made. The id, url
+	/// and poll are filled in from the response.
+	pub entry: Entry,
+	#[serde(skip)]
+	path: PathBuf,
+}
+
+impl Pending {
+	pub fn new(config: &Config, body: String, entry: Entry) -> Self {
+		Self {
+			key: key(),
+			body,
+			created: Utc::now(),
+			entry,
+			path: path(config),
+		}
+	}
+
+	pub fn load(config: &Config) -> Result<Option<Self>> {
+		let path = path(config);
+		if !super::exists(&path)? {
+			return Ok(None);
+		}
+		let file = fs::read_to_string(&path).context(format!(
+			"Failed to read pending post at {:?}.",
+			path
+		))?;
+		let mut pending = toml::de::from_str::<Self>(&file).context(
+			format!("Failed to parse pending post at {:?}.", path),
+		)?;
+		pending.path = path;
+		Ok(Some(pending))
+	}
+
+	pub fn dump(&self) -> Result<()> {
+		if let Some(dir) = self.path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		let data = toml::to_string(&self)?;
+		fs::write(&self.path, data).context(format!(
+			"Failed to save pending post at {:?}.",
+			self.path
+		))
+	}
+
+	/// Forgets the post, once it's been made or given up on.
+	pub fn clear(&self) -> Result<()> {
+		if !super::exists(&self.path)? {
+			return Ok(());
+		}
+		fs::remove_file(&self.path).context(format!(
+			"Failed to remove pending post at {:?}.",
+			self.path
+		))
+	}
+}
+
+fn path(config: &Config) -> PathBuf {
+	config.cache.path.join("pending.toml")
+}
+
+/// A random key for a new post. This deliberately doesn't use the
+/// bot's own rng, which might be seeded to make the same choices
+/// every run.
+fn key() -> String {
+	format!("{:032x}", rand::thread_rng().gen::<u128>())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn saved_until_cleared() {
+		let dir = std::env::temp_dir().join(format!(
+			"inventor_bot_pending_{}",
+			std::process::id()
+		));
+		let config = toml::from_str::<Config>(&format!(
+			"instance = \"example.com\"\ncache.path = {:?}\n",
+			dir
+		))
+		.unwrap();
+		assert!(Pending::load(&config).unwrap().is_none());
+
+		let entry = toml::from_str::<Entry>(
+			"timestamp = \"2024-01-01T00:00:00Z\"\n\
+			inventor = \"Ada\"\n\
+			invention = \"the loom\"\n\
+			template = \"\"\n\
+			status = \"Ada invented the loom\"\n\
+			id = \"\"\n",
+		)
+		.unwrap();
+		let pending =
+			Pending::new(&config, String::from("{}"), entry);
+		pending.dump().unwrap();
+		let loaded = Pending::load(&config).unwrap().unwrap();
+		assert_eq!(loaded.key, pending.key);
+		assert_eq!(loaded.body, "{}");
+		assert_eq!(loaded.created, pending.created);
+		assert_eq!(loaded.entry.status, "Ada invented the loom");
+		// every post gets its own key
+		let other =
+			Pending::new(&config, String::new(), loaded.entry);
+		assert_ne!(other.key, pending.key);
+		assert_eq!(other.key.len(), 32);
+
+		pending.clear().unwrap();
+		assert!(Pending::load(&config).unwrap().is_none());
+		// clearing twice is fine
+		pending.clear().unwrap();
+		fs::remove_dir_all(dir).unwrap();
+	}
+}
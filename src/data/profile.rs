@@ -0,0 +1,151 @@
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use toml;
+
+use super::config::Config;
+
+/// Mastodon only shows four fields, and removes any that are sent
+/// blank, so fields are always sent four at a time to clear out any
+/// that were there before.
+pub const FIELDS: usize = 4;
+
+/// The MIME type of a profile image, going by its extension, or
+/// `None` if it isn't a kind of image instances take.
+pub fn mime(path: &Path) -> Option<&'static str> {
+	let extension = path.extension()?.to_str()?.to_lowercase();
+	match extension.as_str() {
+		"png" => Some("image/png"),
+		"jpg" | "jpeg" => Some("image/jpeg"),
+		"gif" => Some("image/gif"),
+		"webp" => Some("image/webp"),
+		_ => None,
+	}
+}
+
+/// A hex SHA-256 hash, which is what's remembered of each part of the
+/// profile rather than the part itself.
+pub fn hash(data: impl AsRef<[u8]>) -> String {
+	format!("{:x}", Sha256::digest(data))
+}
+
+/// What the profile was set to the last time it was updated, so that
+/// it's only updated again when something has changed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Applied {
+	/// The hash of the name, bio, fields and flags.
+	pub text: Option<String>,
+	pub avatar: Option<String>,
+	pub header: Option<String>,
+	pub updated: Option<DateTime<Utc>>,
+	#[serde(skip)]
+	path: PathBuf,
+}
+
+impl Applied {
+	pub fn load(config: &Config) -> Result<Self> {
+		let path = path(config);
+		if !super::exists(&path)? {
+			return Ok(Self {
+				path,
+				..Self::default()
+			});
+		}
+		let file = fs::read_to_string(&path).context(format!(
+			"Failed to read profile details at {:?}.",
+			path
+		))?;
+		let mut applied = toml::de::from_str::<Self>(&file).context(
+			format!("Failed to parse profile details at {:?}.", path),
+		)?;
+		applied.path = path;
+		Ok(applied)
+	}
+
+	/// Whether a part of the profile, the `"text"`, `"avatar"` or
+	/// `"header"`, has a different hash to when it was last applied.
+	pub fn changed(&self, part: &str, hash: &str) -> bool {
+		let last = match part {
+			"avatar" => &self.avatar,
+			"header" => &self.header,
+			_ => &self.text,
+		};
+		last.as_deref() != Some(hash)
+	}
+
+	/// Remembers the hash of a part of the profile once it's applied.
+	pub fn set(&mut self, part: &str, hash: String) {
+		let last = match part {
+			"avatar" => &mut self.avatar,
+			"header" => &mut self.header,
+			_ => &mut self.text,
+		};
+		*last = Some(hash);
+	}
+
+	/// Saves what's been applied. Like the history, this is kept
+	/// whether or not `cache.enable` is on, since without it the
+	/// whole profile would be sent every time.
+	pub fn dump(&self) -> Result<()> {
+		if let Some(dir) = self.path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		let data = toml::to_string(&self)?;
+		fs::write(&self.path, data).context(format!(
+			"Failed to save profile details at {:?}.",
+			self.path
+		))
+	}
+}
+
+fn path(config: &Config) -> PathBuf {
+	config.cache.path.join("profile.toml")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn hashes() {
+		assert_eq!(
+			hash("abc"),
+			"ba7816bf8f01cfea414140de5dae2223\
+			b00361a396177a9cb410ff61f20015ad"
+		);
+	}
+
+	#[test]
+	fn only_changes_are_applied() {
+		let dir = std::env::temp_dir().join(format!(
+			"inventor_bot_profile_{}",
+			std::process::id()
+		));
+		let config = toml::from_str::<Config>(&format!(
+			"instance = \"example.com\"\ncache.path = {:?}\n",
+			dir
+		))
+		.unwrap();
+		let mut applied = Applied::load(&config).unwrap();
+		assert!(applied.changed("text", &hash("bio")));
+		assert!(applied.changed("avatar", &hash("avatar")));
+
+		applied.set("text", hash("bio"));
+		applied.set("avatar", hash("avatar"));
+		applied.dump().unwrap();
+
+		let applied = Applied::load(&config).unwrap();
+		assert!(!applied.changed("text", &hash("bio")));
+		assert!(applied.changed("text", &hash("new bio")));
+		assert!(!applied.changed("avatar", &hash("avatar")));
+		// the parts are kept apart
+		assert!(applied.changed("header", &hash("avatar")));
+		fs::remove_dir_all(dir).unwrap();
+	}
+}
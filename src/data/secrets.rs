@@ -1,5 +1,7 @@
 use std::{
-	fs::{self, File}, io::Read, path::Path, process, time::{SystemTime, UNIX_EPOCH}
+	fs::{self, File}, io::Read, path::Path, process,
+	sync::{Mutex, OnceLock},
+	time::{SystemTime, UNIX_EPOCH}
 };
 
 use anyhow::{anyhow, Result};
@@ -13,22 +15,45 @@ use rand::prelude::*;
 use serde::{Deserialize, Serialize};
 use toml;
 
-use super::{cache::Cache, config::Config};
-use crate::api::auth::{self, Client};
+use super::{
+	cache::Cache,
+	config::{Backend, Config},
+};
+use crate::api::{
+	auth::{self, Client},
+	bluesky::{self, Session},
+	misskey,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Secrets {
+	/// Which kind of instance the token is for.
+	#[serde(default)]
+	pub backend: Backend,
 	pub client: Option<Client>,
 	pub token: String,
+	/// Space separated, like in OAuth requests.
+	pub scopes: Option<String>,
+	/// Bluesky's access and refresh tokens, which change as the
+	/// session is refreshed.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub session: Option<Mutex<Session>>,
 	#[serde(default, skip_serializing)]
 	pub new: bool,
 }
 
+/// The password is only asked for once, and used for every account.
+static PASSWORD: OnceLock<String> = OnceLock::new();
+
 fn get_password() -> String {
-	rpassword::prompt_password("Password: ").unwrap()
+	PASSWORD
+		.get_or_init(|| {
+			rpassword::prompt_password("Password: ").unwrap()
+		})
+		.clone()
 }
 
-fn encrypt<'a>(content: impl AsRef<str>) -> Result<Vec<u8>> {
+fn encrypt(content: impl AsRef<str>) -> Result<Vec<u8>> {
 	let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
 	let mut rng = StdRng::seed_from_u64(now);
 
@@ -60,33 +85,81 @@ fn decrypt(content: impl Read) -> Result<String> {
 
 impl Secrets {
 	// GET /api/v1/apps/verify_credentials
-	pub fn verify(&self, instance: impl AsRef<str>) -> Result<()> {
-		auth::verify(&self.token, instance)
+	pub fn verify(&self, config: &Config) -> Result<()> {
+		let instance = &config.instance;
+		match self.backend {
+			Backend::Mastodon => auth::verify(&self.token, instance),
+			Backend::Misskey => {
+				misskey::verify(&self.token, instance)
+			},
+			Backend::Bluesky => bluesky::verify(config, self),
+		}
 	}
 
 	// POST /oauth/revoke
-	pub fn revoke(self, instance: impl AsRef<str>) -> Result<()> {
+	pub fn revoke(self, config: &Config) -> Result<()> {
+		match self.backend {
+			Backend::Mastodon => {},
+			Backend::Misskey => {
+				log::warn!(
+					"Misskey tokens can't be revoked by the bot, \
+					remove it from the access tokens in your \
+					settings instead."
+				);
+				return Ok(());
+			},
+			Backend::Bluesky => {
+				return bluesky::logout(config, &self)
+			},
+		}
 		// Client will only be None if a token is passed via the command line,
 		// In which case revoke is never called.
 		self.client
 			.expect("This should never happen")
-			.revoke(self.token, instance)
+			.revoke(self.token, &config.instance)
+	}
+
+	/// Scopes the config needs that this token wasn't given.
+	pub fn missing_scopes(&self, config: &Config) -> Vec<String> {
+		let granted =
+			self.scopes.as_deref().unwrap_or(auth::LEGACY_SCOPES);
+		let granted = granted.split(' ').collect::<Vec<_>>();
+		auth::scopes(config)
+			.split(' ')
+			.filter(|scope| {
+				!scope.is_empty() && !granted.contains(scope)
+			})
+			.map(str::to_owned)
+			.collect()
 	}
 
 	pub fn new(config: &Config) -> Result<Self> {
-		let client = Client::new(config)?;
-		let token = client.token(config)?;
+		let (client, token, session) = match config.backend {
+			Backend::Mastodon => {
+				let client = Client::new(config)?;
+				let token = client.token(config)?;
+				(Some(client), token, None)
+			},
+			Backend::Misskey => (None, misskey::login(config)?, None),
+			Backend::Bluesky => {
+				let session = bluesky::login(config)?;
+				(None, String::new(), Some(Mutex::new(session)))
+			},
+		};
 		Ok(Self {
 			new: true,
-			client: Some(client),
+			backend: config.backend,
+			client,
 			token,
+			scopes: Some(auth::scopes(config)),
+			session,
 		})
 	}
 
 	fn get(path: impl AsRef<Path>, protect: bool) -> Result<Self> {
 		let path = path.as_ref();
-		super::is_valid(&path, true)?;
-		let mut file = File::open(&path)?;
+		super::is_valid(path, true)?;
+		let mut file = File::open(path)?;
 		let file = match protect {
 			true => {
 				decrypt(file).unwrap_or_else(|e| {
@@ -106,7 +179,7 @@ impl Secrets {
 	pub fn load(config: &Config) -> Option<Self> {
 		log::info!("Loading data cache");
 		let cache =
-			Cache::load(&config.cache.path.join("cache.toml"))?;
+			Cache::load(config.cache.path.join("cache.toml"))?;
 		if cache.instance != config.instance {
 			log::warn!("Data cache is for instance {}, current instance is {}. Ignoring.",
 				cache.instance, config.instance);
@@ -142,6 +215,15 @@ impl Secrets {
 			Ok(true) => {},
 		};
 		match Self::get(path, protect) {
+			Ok(secrets) if secrets.backend != config.backend => {
+				log::warn!(
+					"Data cache is for a {:?} token, but the instance is \
+					set to use {:?}. Ignoring.",
+					secrets.backend,
+					config.backend
+				);
+				None
+			},
 			Ok(secrets) => Some(secrets),
 			Err(e) => {
 				log::error!("{e}");
@@ -156,11 +238,11 @@ impl Secrets {
 		}
 	}
 
-	pub fn dump(self, config: &Config) -> Result<()> {
+	pub fn dump(&self, config: &Config) -> Result<()> {
 		log::info!("Storing data cache.");
 		fs::create_dir_all(&config.cache.path)?;
 		let path = &config.cache.path.join("data");
-		let data = toml::to_string(&self)?;
+		let data = toml::to_string(self)?;
 		let data = if config.cache.protect {
 			encrypt(data)?
 		} else {
@@ -172,7 +254,7 @@ impl Secrets {
 				instance: config.instance.clone(),
 				protect: config.cache.protect,
 			}
-			.dump(&config.cache.path.join("cache.toml"))?;
+			.dump(config.cache.path.join("cache.toml"))?;
 		}
 		Ok(res?)
 	}
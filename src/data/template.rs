@@ -0,0 +1,223 @@
+use std::fmt::Write;
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+pub const DEFAULT: &str =
+	"I can't believe {inventor} invented {invention}";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Slot {
+	Inventor,
+	Invention,
+	Date,
+	Count,
+}
+
+impl Slot {
+	const ALL: [(&'static str, Slot); 4] = [
+		("inventor", Slot::Inventor),
+		("invention", Slot::Invention),
+		("date", Slot::Date),
+		("count", Slot::Count),
+	];
+
+	fn parse(name: &str) -> Option<Self> {
+		Self::ALL
+			.iter()
+			.find(|(n, _)| *n == name)
+			.map(|(_, slot)| *slot)
+	}
+}
+
+#[derive(Debug)]
+enum Part {
+	Text(String),
+	Slot(Slot),
+}
+
+/// The values to substitute into a template's slots.
+pub struct Values<'a> {
+	pub inventor: &'a str,
+	pub invention: &'a str,
+	pub date: &'a str,
+	pub count: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawTemplate {
+	Plain(String),
+	Weighted { text: String, weight: u32 },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "RawTemplate")]
+pub struct Template {
+	pub text: String,
+	pub weight: u32,
+	parts: Vec<Part>,
+}
+
+impl TryFrom<RawTemplate> for Template {
+	type Error = anyhow::Error;
+
+	fn try_from(raw: RawTemplate) -> Result<Self> {
+		let (text, weight) = match raw {
+			RawTemplate::Plain(text) => (text, 1),
+			RawTemplate::Weighted { text, weight } => (text, weight),
+		};
+		Self::new(text, weight)
+	}
+}
+
+impl Template {
+	pub fn new(text: String, weight: u32) -> Result<Self> {
+		let parts = parse(&text)
+			.map_err(|e| anyhow!("{e} in template {text:?}"))?;
+		Ok(Self {
+			text,
+			weight,
+			parts,
+		})
+	}
+
+	pub fn has(&self, slot: Slot) -> bool {
+		self.parts
+			.iter()
+			.any(|part| matches!(part, Part::Slot(s) if *s == slot))
+	}
+
+	pub fn render(&self, values: &Values) -> String {
+		let mut out = String::new();
+		for part in &self.parts {
+			match part {
+				Part::Text(text) => out.push_str(text),
+				Part::Slot(Slot::Inventor) => {
+					out.push_str(values.inventor)
+				},
+				Part::Slot(Slot::Invention) => {
+					out.push_str(values.invention)
+				},
+				Part::Slot(Slot::Date) => out.push_str(values.date),
+				Part::Slot(Slot::Count) => {
+					let _ = write!(out, "{}", values.count);
+				},
+			}
+		}
+		out
+	}
+}
+
+// `{name}` is a slot, `{{` and `}}` are literal braces.
+fn parse(text: &str) -> Result<Vec<Part>> {
+	let mut parts = Vec::new();
+	let mut literal = String::new();
+	let mut chars = text.chars().peekable();
+	while let Some(c) = chars.next() {
+		match (c, chars.peek()) {
+			('{', Some('{')) | ('}', Some('}')) => {
+				chars.next();
+				literal.push(c);
+			},
+			('{', _) => {
+				let mut name = String::new();
+				loop {
+					match chars.next() {
+						Some('}') => break,
+						Some(c) => name.push(c),
+						None => {
+							return Err(anyhow!(
+								"Unclosed `{{{name}`"
+							))
+						},
+					}
+				}
+				let slot =
+					Slot::parse(name.trim()).ok_or_else(|| {
+						let valid = Slot::ALL
+							.iter()
+							.map(|(n, _)| format!("{{{n}}}"))
+							.collect::<Vec<_>>()
+							.join(", ");
+						anyhow!(
+							"Unknown slot `{{{name}}}` \
+							(valid slots are {valid})"
+						)
+					})?;
+				if !literal.is_empty() {
+					parts.push(Part::Text(std::mem::take(
+						&mut literal,
+					)));
+				}
+				parts.push(Part::Slot(slot));
+			},
+			('}', _) => {
+				return Err(anyhow!(
+					"Unmatched `}}` (use `}}}}` for a literal brace)"
+				))
+			},
+			_ => literal.push(c),
+		}
+	}
+	if !literal.is_empty() {
+		parts.push(Part::Text(literal));
+	}
+	Ok(parts)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const VALUES: Values = Values {
+		inventor: "Ada Lovelace",
+		invention: "the toaster",
+		date: "2024-01-01",
+		count: 42,
+	};
+
+	#[test]
+	fn renders_every_slot() {
+		let template = Template::new(
+			String::from("#{count} {inventor}: {invention}, {date}"),
+			1,
+		)
+		.unwrap();
+		assert_eq!(
+			template.render(&VALUES),
+			"#42 Ada Lovelace: the toaster, 2024-01-01"
+		);
+		assert!(template.has(Slot::Count));
+	}
+
+	#[test]
+	fn slots_can_have_spaces() {
+		let template =
+			Template::new(String::from("{ inventor }"), 1).unwrap();
+		assert_eq!(template.render(&VALUES), "Ada Lovelace");
+	}
+
+	#[test]
+	fn literal_braces() {
+		let text = String::from("{{{invention}}}");
+		let template = Template::new(text, 1).unwrap();
+		assert_eq!(template.render(&VALUES), "{the toaster}");
+		assert!(!template.has(Slot::Inventor));
+	}
+
+	#[test]
+	fn unknown_slot() {
+		let e = Template::new(String::from("{inventer}"), 1)
+			.unwrap_err()
+			.to_string();
+		assert!(e.contains("Unknown slot `{inventer}`"), "{e}");
+		assert!(e.contains("{inventor}"), "{e}");
+	}
+
+	#[test]
+	fn unbalanced_braces() {
+		assert!(Template::new(String::from("{inventor"), 1).is_err());
+		assert!(Template::new(String::from("inventor}"), 1).is_err());
+	}
+}
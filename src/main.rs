@@ -1,74 +1,442 @@
-use std::{sync::mpsc, time::Duration};
+use std::{
+	sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+	thread,
+	time::{Duration, Instant},
+};
 
-use anyhow::{Context, Result};
-use api::post::{self, PostCfg};
-use rand::distributions::Uniform;
+use anyhow::{anyhow, Context, Result};
+use api::{
+	auth, mentions, nodeinfo, polls,
+	post::{self, Mirror, PostCfg},
+	profile, prune, schedule, stats,
+	streaming::{self, Event},
+	words::Words,
+};
+use rand::{
+	distributions::{Distribution, WeightedIndex},
+	rngs::StdRng,
+	Rng, SeedableRng,
+};
 
-use crate::data::{config::Config, secrets::Secrets};
+use crate::{
+	cli::{Action, Command},
+	data::{
+		config::{Config, Seed},
+		english::Dictionary,
+		filter::Filter,
+		history::History,
+		mentions::Mentions,
+		secrets::Secrets,
+	},
+};
 
 mod api;
 mod cli;
 mod data;
 
 fn main() -> Result<()> {
-	// initialisation
 	let command = cli::init()?;
-	let has_token = command.token.is_some();
-	let config = Config::get(&command)?;
+	// The main thread has a name, which would be put in front of
+	// every log. Running in an unnamed thread instead means only the
+	// threads for each of several accounts are named in the logs.
+	thread::Builder::new()
+		.spawn(move || start(command))?
+		.join()
+		.map_err(|_| anyhow!("The bot panicked."))?
+}
+
+/// An account that's been logged in to.
+struct Account<'a> {
+	config: &'a Config,
+	secrets: Option<Secrets>,
+	/// Whether the token was given rather than logged in for, in
+	/// which case it isn't revoked at the end.
+	has_token: bool,
+}
+
+fn start(command: Command) -> Result<()> {
+	// initialisation
+	let configs = Config::get(&command)?;
+
+	if let Some(Action::History(args)) = &command.action {
+		let several = configs.len() > 1;
+		for config in &configs {
+			if let (Some(name), true) = (&config.name, several) {
+				println!("{name}:");
+			}
+			History::load(config)?
+				.print(args.query.as_deref(), args.limit);
+		}
+		return Ok(());
+	}
+	if let Some(Action::Stats) = &command.action {
+		let several = configs.len() > 1;
+		for (i, config) in configs.iter().enumerate() {
+			if let (Some(name), true) = (&config.name, several) {
+				if i > 0 {
+					println!();
+				}
+				println!("{name}:");
+			}
+			stats::report(&History::load(config)?);
+		}
+		return Ok(());
+	}
+
 	api::init()?;
 
-	// authorisation
-	let secrets = match (config.cache.enable, command.token) {
-		(_, Some(token)) => Some(Secrets {
-			new: true,
-			client: None,
-			token,
-		}),
-		(true, None) => Secrets::load(&config),
-		(false, None) => None,
-	};
-	let secrets = match secrets {
-		Some(secrets) => secrets,
-		None => Secrets::new(&config)?,
-	};
-	secrets.verify(&config.instance)?;
+	// authorisation, one account at a time, since logging in may need
+	// a password or a browser
+	let mut accounts = Vec::new();
+	for config in &configs {
+		let token = command.token.clone().or(config.token.clone());
+		let has_token = token.is_some();
+		let secrets = match config.dry_run {
+			true => None,
+			false => Some(authorise(config, token).context(
+				match &config.name {
+					Some(name) => {
+						format!("Failed to log in as {name:?}.")
+					},
+					None => String::from("Failed to log in."),
+				},
+			)?),
+		};
+		accounts.push(Account {
+			config,
+			secrets,
+			has_token,
+		});
+	}
+
+	// accounts which are cross-posted to are served along with the
+	// account posting to them, rather than in threads of their own
+	let sources = accounts
+		.iter()
+		.filter(|account| {
+			!accounts.iter().any(|other| {
+				account.config.name.as_ref().is_some_and(|name| {
+					other.config.crosspost.contains(name)
+				})
+			})
+		})
+		.map(|account| {
+			let mirrors = accounts
+				.iter()
+				.filter(|other| {
+					other.config.name.as_ref().is_some_and(|name| {
+						account.config.crosspost.contains(name)
+					})
+				})
+				.collect::<Vec<_>>();
+			(account, mirrors)
+		})
+		.collect::<Vec<_>>();
 
-	// exit handler
-	let (send, recv) = mpsc::channel::<()>();
+	// events
+	let (senders, receivers) = sources
+		.iter()
+		.map(|_| mpsc::channel::<Event>())
+		.unzip::<_, _, Vec<_>, Vec<_>>();
+	let exit = senders.clone();
 	if let Err(e) = ctrlc::set_handler(move || {
-		let _ = send.send(());
+		for send in &exit {
+			let _ = send.send(Event::Exit);
+		}
 	}) {
 		log::error!("Failed to set Ctrl-C handler: {e}");
 	}
 
 	// start
+	let results = thread::scope(|scope| {
+		let handles = sources
+			.iter()
+			.zip(senders.into_iter().zip(receivers))
+			.map(|((account, mirrors), (send, recv))| {
+				let mut builder = thread::Builder::new();
+				if let Some(name) = &account.config.name {
+					builder = builder.name(name.clone());
+				}
+				builder.spawn_scoped(scope, || {
+					serve(account, mirrors, &command, send, recv)
+				})
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+		Ok::<_, anyhow::Error>(
+			handles
+				.into_iter()
+				.map(|handle| {
+					handle
+						.join()
+						.map_err(|_| anyhow!("The bot panicked."))?
+				})
+				.collect::<Vec<_>>(),
+		)
+	})?;
+
+	// shutdown
+	let configs = sources
+		.iter()
+		.map(|(account, _)| account.config)
+		.collect::<Vec<_>>();
+	for account in accounts {
+		let config = account.config;
+		let Some(secrets) = account.secrets else {
+			continue;
+		};
+		match (config.cache.enable, secrets.new, account.has_token) {
+			(true, true, _) => secrets
+				.dump(config)
+				.context("Failed to save cache.")?,
+			(false, _, false) => secrets.revoke(config)?,
+			_ => {},
+		}
+	}
+
+	let mut result = Ok(());
+	for (config, account_result) in configs.iter().zip(results) {
+		let Err(e) = account_result else {
+			continue;
+		};
+		let e = match &config.name {
+			Some(name) => e.context(format!("{name:?} failed.")),
+			None => e,
+		};
+		// only one error can be returned, so the rest are logged
+		if let Err(other) = std::mem::replace(&mut result, Err(e)) {
+			log::error!("{other:?}");
+		}
+	}
+	result
+}
+
+/// Does whatever was asked for with a single account.
+fn serve(
+	account: &Account,
+	mirrors: &[&Account],
+	command: &Command,
+	send: Sender<Event>,
+	recv: Receiver<Event>,
+) -> Result<()> {
+	let config = account.config;
+	let secrets = account.secrets.as_ref();
+	let mut history = History::load(config)?;
+	if let Err(e) = post::resume(config, secrets, &mut history) {
+		log::error!("{e}");
+	}
+	let mirrors = mirrors
+		.iter()
+		.map(|mirror| {
+			let mut history = History::load(mirror.config)?;
+			let secrets = mirror.secrets.as_ref();
+			let resumed =
+				post::resume(mirror.config, secrets, &mut history);
+			if let Err(e) = resumed {
+				log::error!("{e}");
+			}
+			Ok(Mirror {
+				config: mirror.config,
+				secrets,
+				history,
+				features: nodeinfo::detect(mirror.config),
+			})
+		})
+		.collect::<Result<Vec<_>>>()?;
+	let words = Words::new(config)?;
+	let dictionary = Dictionary::new(config)?;
+	let filter = Filter::new(config)?;
+	let dist =
+		WeightedIndex::new(config.inventors.iter().map(|i| i.weight))
+			.context("Invalid inventor weights.")?;
+
+	let serving = command.action.is_none();
+	let mentions =
+		match (serving && config.mentions.enable, config.dry_run) {
+			(true, false) => Some(Mentions::load(config)?),
+			(true, true) => {
+				log::warn!(
+					"Mentions are not answered during a dry run."
+				);
+				None
+			},
+			(false, _) => None,
+		};
+	if let (Some(_), Some(secrets)) = (&mentions, secrets) {
+		if config.mentions.streaming {
+			streaming::spawn(&config.instance, &secrets.token, send)?;
+		}
+	}
+
 	let mut cfg = PostCfg {
-		config: &config,
-		secrets: &secrets,
-		rng: rand::thread_rng(),
-		dist: Uniform::new(0, config.inventors.len()),
+		config,
+		secrets,
+		words,
+		dictionary,
+		filter,
+		count: history.posts.len() as u64,
+		history,
+		rng: match config.seed {
+			Some(Seed::Fixed(seed)) => StdRng::seed_from_u64(seed),
+			_ => StdRng::from_entropy(),
+		},
+		dist,
+		adaptive: None,
+		day: None,
+		features: nodeinfo::detect(config),
+		mirrors,
 	};
+	if let Err(e) = stats::adapt(&mut cfg) {
+		log::error!("{e}");
+	}
+	match &command.action {
+		Some(Action::Schedule(action)) => {
+			schedule::run(&mut cfg, action)
+		},
+		Some(Action::Prune(args)) => prune::run(&mut cfg, args),
+		_ => {
+			if let Err(e) = profile::tick(&cfg) {
+				log::error!("{e}");
+			}
+			run(&mut cfg, mentions, recv);
+			Ok(())
+		},
+	}
+}
+
+/// Posts, answers mentions, and announces poll results until there's
+/// nothing left to do or Ctrl-C is pressed.
+fn run<T, D>(
+	cfg: &mut PostCfg<T, D>,
+	mut mentions: Option<Mentions>,
+	recv: Receiver<Event>,
+) where
+	T: Rng + SeedableRng,
+	D: Distribution<usize>,
+{
+	let config = cfg.config;
 	let delay = config.repeat.map(|r| Duration::from_secs(r * 60));
+	let poll = Duration::from_secs(config.mentions.interval * 60);
+	let due = |at: Option<Instant>| {
+		at.is_some_and(|at| Instant::now() >= at)
+	};
+	// one-off runs still announce any results that are due, but don't
+	// wait around for more
+	let stay = delay.is_some() || mentions.is_some();
+	let mut next_post = Some(Instant::now());
+	let mut next_results =
+		cfg.history.open_polls().next().map(|_| Instant::now());
+	let mut next_poll = mentions.as_ref().map(|_| Instant::now());
+	let mut streaming = false;
 	loop {
-		if let Err(e) = post::post(&mut cfg) {
-			log::error!("{e}");
+		if due(next_results) {
+			if let Err(e) = polls::announce(cfg) {
+				log::error!("{e}");
+			}
+			next_results = polls::next(&cfg.history);
+		}
+		if due(next_post) {
+			if let Err(e) = post::post(cfg) {
+				log::error!("{e}");
+			}
+			if let Err(e) = prune::tick(cfg) {
+				log::error!("{e}");
+			}
+			if let Err(e) = stats::tick(cfg) {
+				log::error!("{e}");
+			}
+			if let Err(e) = profile::tick(cfg) {
+				log::error!("{e}");
+			}
+			next_post = delay.map(|delay| Instant::now() + delay);
+			next_results = polls::next(&cfg.history);
 		}
-		match delay {
-			Some(delay) => {
-				if recv.recv_timeout(delay).is_ok() {
-					break;
+		if let Some(mentions) = &mut mentions {
+			if due(next_poll) {
+				if let Err(e) = mentions::check(cfg, mentions) {
+					log::error!("{e}");
 				}
-			},
-			None => break,
+				// while streaming, only check when told to
+				next_poll = match streaming {
+					true => None,
+					false => Some(Instant::now() + poll),
+				};
+			}
 		}
-	}
 
-	// shutdown
-	match (config.cache.enable, secrets.new, has_token) {
-		(true, true, _) => secrets.dump(&config).context("Failed to save cache.")?,
-		(false, _, false) => secrets.revoke(&config.instance)?,
-		_ => {}
+		let wake =
+			[next_post, next_poll, next_results.filter(|_| stay)]
+				.into_iter()
+				.flatten()
+				.min();
+		let event = match (wake, mentions.is_some()) {
+			(Some(wake), _) => match recv.recv_timeout(
+				wake.saturating_duration_since(Instant::now()),
+			) {
+				Ok(event) => event,
+				Err(RecvTimeoutError::Timeout) => continue,
+				Err(RecvTimeoutError::Disconnected) => break,
+			},
+			(None, true) => match recv.recv() {
+				Ok(event) => event,
+				Err(_) => break,
+			},
+			(None, false) => break,
+		};
+		match event {
+			Event::Exit => break,
+			Event::Connected => {
+				streaming = true;
+				// catch up on anything missed while disconnected
+				next_poll = Some(Instant::now());
+			},
+			Event::Disconnected => {
+				streaming = false;
+				next_poll = Some(Instant::now() + poll);
+			},
+			Event::Mention { acct } => {
+				log::info!("Mentioned by {acct}.");
+				next_poll = Some(Instant::now());
+			},
+			Event::Follow { acct } => {
+				log::info!("Followed by {acct}.")
+			},
+			Event::Favourite { acct, status } => {
+				log::info!("{acct} favourited {status}.")
+			},
+		}
 	}
+}
 
-	Ok(())
+fn authorise(
+	config: &Config,
+	token: Option<String>,
+) -> Result<Secrets> {
+	let secrets = match (config.cache.enable, token) {
+		(_, Some(token)) => Some(Secrets {
+			new: true,
+			backend: config.backend,
+			client: None,
+			token,
+			scopes: Some(auth::scopes(config)),
+			session: None,
+		}),
+		(true, None) => Secrets::load(config).filter(|secrets| {
+			let missing = secrets.missing_scopes(config);
+			if !missing.is_empty() {
+				log::warn!(
+					"The cached token is missing the scopes {}, \
+					logging in again.",
+					missing.join(", ")
+				);
+			}
+			missing.is_empty()
+		}),
+		(false, None) => None,
+	};
+	let secrets = match secrets {
+		Some(secrets) => secrets,
+		None => Secrets::new(config)?,
+	};
+	secrets.verify(config)?;
+	Ok(secrets)
 }